@@ -0,0 +1,19 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+/// Where and how to upload an attachment
+#[derive(Debug, Deserialize)]
+pub struct Policy {
+    pub attachment: Attachment,
+    /// Fields sent with the file
+    pub form: HashMap<String, String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Attachment {
+    /// URL to upload the file to
+    pub endpoint: String,
+    /// URL of the uploaded file
+    pub url: String,
+}
@@ -5,10 +5,17 @@ pub enum Error {
     #[error("error: {}, message: {}", .0.error, .0.message)]
     ApiError(ErrorResponse),
 
-    #[error("HTTP Error: {}", 0)]
+    #[error("HTTP Error: {}", .0)]
     HttpError(reqwest::Error),
 }
 
+impl Error {
+    /// Whether the request is rejected by the rate limit
+    pub fn is_rate_limited(&self) -> bool {
+        matches!(self, Self::ApiError(e) if e.error == "too_many_requests")
+    }
+}
+
 impl From<reqwest::Error> for Error {
     fn from(error: reqwest::Error) -> Self {
         Self::HttpError(error)
@@ -1,12 +1,16 @@
 use std::collections::HashMap;
 use std::fmt::{self, Debug};
+use std::sync::Mutex;
 
-use reqwest::{self, Client, ClientBuilder, Url};
+use chrono::{prelude::Local, DateTime, TimeZone};
+use reqwest::{self, Client, ClientBuilder, Response, Url};
 use serde::Deserialize;
 
+pub mod attachment;
 pub mod error;
 pub mod post;
 pub mod team;
+pub mod user;
 
 use error::{Error, ErrorResponse};
 
@@ -17,6 +21,16 @@ const BASE_URL: &str = "https://api.esa.io/v1";
 pub struct Esa {
     client: Client,
     team: Team,
+    rate_limit: Mutex<Option<RateLimit>>,
+}
+
+/// Rate limit told by the last response
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    pub limit: i32,
+    pub remaining: i32,
+    /// When the remaining count is reset
+    pub reset: DateTime<Local>,
 }
 
 impl Esa {
@@ -24,7 +38,38 @@ impl Esa {
         let client = ClientBuilder::new()
             .build()
             .expect("failed to build HTTP client");
-        Esa { client, team }
+        Esa {
+            client,
+            team,
+            rate_limit: Mutex::new(None),
+        }
+    }
+
+    /// Rate limit told by the last response. `None` before any request
+    pub fn rate_limit(&self) -> Option<RateLimit> {
+        *self.rate_limit.lock().expect("rate limit lock is poisoned")
+    }
+
+    fn record_rate_limit(&self, response: &Response) {
+        let header = |name: &str| -> Option<i64> {
+            response.headers().get(name)?.to_str().ok()?.parse().ok()
+        };
+        let rate_limit = (|| {
+            Some(RateLimit {
+                limit: header("X-RateLimit-Limit")? as i32,
+                remaining: header("X-RateLimit-Remaining")? as i32,
+                reset: Local
+                    .timestamp_opt(header("X-RateLimit-Reset")?, 0)
+                    .single()?,
+            })
+        })();
+        if let Some(rate_limit) = rate_limit {
+            *self.rate_limit.lock().expect("rate limit lock is poisoned") = Some(rate_limit);
+        }
+    }
+
+    pub fn team_id(&self) -> &TeamId {
+        &self.team.id
     }
 
     pub async fn team(&self) -> Result<team::Team> {
@@ -34,6 +79,7 @@ impl Esa {
             .bearer_auth(self.team.access_token.to_string())
             .send()
             .await?;
+        self.record_rate_limit(&response);
         if response.status().is_success() {
             let team = response
                 .json::<team::Team>()
@@ -49,13 +95,49 @@ impl Esa {
         }
     }
 
+    pub async fn user(&self) -> Result<user::User> {
+        let response = self
+            .client
+            .get(format!("{}/user", BASE_URL))
+            .bearer_auth(self.team.access_token.to_string())
+            .send()
+            .await?;
+        self.record_rate_limit(&response);
+
+        if response.status().is_success() {
+            let user = response.json::<user::User>().await?;
+            Ok(user)
+        } else {
+            let error = response.json::<ErrorResponse>().await?;
+            Err(Error::ApiError(error))
+        }
+    }
+
     pub async fn post(&self, id: i32) -> Result<post::Post> {
+        self.post_with(id, Vec::new()).await
+    }
+
+    /// Post with the optional data such as comments
+    pub async fn post_with(&self, id: i32, include: Vec<post::Include>) -> Result<post::Post> {
+        let mut query_string: HashMap<&str, String> = HashMap::new();
+        if !include.is_empty() {
+            let include: Vec<String> = include.into_iter().map(Into::into).collect();
+            query_string.insert("include", include.join(","));
+        }
+        let url = Url::parse_with_params(
+            format!("{}/teams/{}/posts/{}", BASE_URL, self.team.id, id).as_str(),
+            query_string,
+        )
+        // ここで panic するということは URL の組み立て方がおかしい
+        .unwrap_or_else(|e| panic!("url parse error: {:?}", e));
+
         let response = self
             .client
-            .get(format!("{}/teams/{}/posts/{}", BASE_URL, self.team.id, id))
+            .get(url)
             .bearer_auth(self.team.access_token.to_string())
             .send()
             .await?;
+        self.record_rate_limit(&response);
 
         if response.status().is_success() {
             let post = response.json::<post::Post>().await?;
@@ -80,6 +162,12 @@ impl Esa {
             query_string.insert("sort", s);
             query_string.insert("order", o);
         }
+        if let Some(page) = query.page {
+            query_string.insert("page", page.to_string());
+        }
+        if let Some(per_page) = query.per_page {
+            query_string.insert("per_page", per_page.to_string());
+        }
 
         let url = Url::parse_with_params(
             format!("{}/teams/{}/posts", BASE_URL, self.team.id).as_str(),
@@ -94,6 +182,7 @@ impl Esa {
             .bearer_auth(self.team.access_token.to_string())
             .send()
             .await?;
+        self.record_rate_limit(&response);
         if response.status().is_success() {
             let posts = response.json::<post::Posts>().await?;
             Ok(posts)
@@ -124,6 +213,7 @@ impl Esa {
             .json(&new_post)
             .send()
             .await?;
+        self.record_rate_limit(&response);
 
         if response.status().is_success() {
             let post_created = response.json::<post::NewPostCreated>().await?;
@@ -142,6 +232,7 @@ impl Esa {
             .json(post)
             .send()
             .await?;
+        self.record_rate_limit(&response);
 
         if response.status().is_success() {
             let post_edited = response.json::<post::PostEdited>().await?;
@@ -152,6 +243,89 @@ impl Esa {
         }
     }
 
+    pub async fn create_comment(&self, post_id: i32, body_md: String) -> Result<post::Comment> {
+        let new_comment = post::NewCommentRequest {
+            comment: post::NewComment { body_md },
+        };
+        let response = self
+            .client
+            .post(format!(
+                "{}/teams/{}/posts/{}/comments",
+                BASE_URL, self.team.id, post_id
+            ))
+            .bearer_auth(self.team.access_token.to_string())
+            .json(&new_comment)
+            .send()
+            .await?;
+        self.record_rate_limit(&response);
+
+        if response.status().is_success() {
+            let comment = response.json::<post::Comment>().await?;
+            Ok(comment)
+        } else {
+            let error = response.json::<ErrorResponse>().await?;
+            Err(Error::ApiError(error))
+        }
+    }
+
+    /// Uploads the file and returns its URL
+    pub async fn upload_attachment(
+        &self,
+        name: &str,
+        content_type: &str,
+        content: Vec<u8>,
+    ) -> Result<String> {
+        let response = self
+            .client
+            .post(format!(
+                "{}/teams/{}/attachments/policies",
+                BASE_URL, self.team.id
+            ))
+            .bearer_auth(self.team.access_token.to_string())
+            .form(&[
+                ("type", content_type),
+                ("size", &content.len().to_string()),
+                ("name", name),
+            ])
+            .send()
+            .await?;
+        self.record_rate_limit(&response);
+        if !response.status().is_success() {
+            let error = response.json::<ErrorResponse>().await?;
+            return Err(Error::ApiError(error));
+        }
+        let policy = response.json::<attachment::Policy>().await?;
+
+        // 署名付きのフォームでストレージに直接アップロードする
+        let mut form = reqwest::multipart::Form::new();
+        for (key, value) in policy.form {
+            form = form.text(key, value);
+        }
+        let file = reqwest::multipart::Part::bytes(content)
+            .file_name(name.to_string())
+            .mime_str(content_type)?;
+        form = form.part("file", file);
+        self.client
+            .post(&policy.attachment.endpoint)
+            .multipart(form)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(policy.attachment.url)
+    }
+
+    /// Downloads the attached file.
+    /// The access token is sent only to the team's own domain
+    pub async fn download_attachment(&self, url: &str) -> Result<Vec<u8>> {
+        let team_url = format!("https://{}.esa.io/", self.team.id);
+        let mut request = self.client.get(url);
+        if url.starts_with(&team_url) {
+            request = request.bearer_auth(self.team.access_token.to_string());
+        }
+        let response = request.send().await?.error_for_status()?;
+        Ok(response.bytes().await?.to_vec())
+    }
+
     pub async fn delete_post(&self, id: i32) -> Result<()> {
         let response = self
             .client
@@ -159,6 +333,7 @@ impl Esa {
             .bearer_auth(self.team.access_token.to_string())
             .send()
             .await?;
+        self.record_rate_limit(&response);
 
         if response.status().is_success() {
             Ok(())
@@ -1,7 +1,7 @@
 use chrono::{prelude::Local, DateTime};
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Post {
     pub number: i32,
     pub name: String,
@@ -26,7 +26,12 @@ pub struct Post {
     pub watchers_count: i32,
     pub star: bool,
     pub watch: bool,
-    // TODO: comments, stargazers を追加する
+    /// Set when requested with `Include::Comments`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comments: Option<Vec<Comment>>,
+    /// Set when requested with `Include::Stargazers`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stargazers: Option<Vec<Stargazer>>,
 }
 
 impl Post {
@@ -63,17 +68,31 @@ impl Post {
             }),
         }
     }
+
+    /// Edits only name, tags and category. Body and WIP state are kept
+    pub fn edit_metadata(
+        self,
+        name: String,
+        tags: Vec<String>,
+        category: Option<String>,
+        message: Option<String>,
+    ) -> EditedPost {
+        let wip = self.wip;
+        self.edit(name, None, Some(tags), category, wip, message)
+    }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct Writer {
+    /// Not set for stargazers
+    #[serde(default)]
     pub myself: bool,
     pub name: String,
     pub screen_name: String,
     pub icon: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub enum Kind {
     #[serde(rename = "stock")]
     Stock,
@@ -81,6 +100,41 @@ pub enum Kind {
     Flow,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Comment {
+    pub id: i32,
+    pub body_md: String,
+    pub body_html: String,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    pub url: String,
+    pub created_by: Writer,
+    #[serde(default)]
+    pub stargazers_count: i32,
+    #[serde(default)]
+    pub star: bool,
+    /// Set when requested with `Include::CommentStargazers`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stargazers: Option<Vec<Stargazer>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewComment {
+    pub body_md: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NewCommentRequest {
+    pub comment: NewComment,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Stargazer {
+    pub created_at: DateTime<Local>,
+    pub body: Option<String>,
+    pub user: Writer,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Posts {
     pub posts: Vec<Post>,
@@ -92,20 +146,37 @@ pub struct Posts {
     pub max_per_page: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SearchQuery {
     pub q: Option<String>,
     pub include: Option<Vec<Include>>,
     pub sort: Option<Sort>,
+    pub page: Option<i32>,
+    pub per_page: Option<i32>,
 }
 
 impl SearchQuery {
     pub fn new(q: Option<String>, include: Option<Vec<Include>>, sort: Option<Sort>) -> Self {
-        SearchQuery { q, include, sort }
+        SearchQuery {
+            q,
+            include,
+            sort,
+            page: None,
+            per_page: None,
+        }
+    }
+
+    /// Requests the given page. `per_page` is up to 100
+    pub fn paginate(self, page: i32, per_page: i32) -> Self {
+        SearchQuery {
+            page: Some(page),
+            per_page: Some(per_page),
+            ..self
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Include {
     /// スターを含む
     Stargazers,
@@ -139,7 +210,7 @@ impl From<Include> for String {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Sort {
     /// 更新日時（デフォルト）
     Updated(Order),
@@ -193,18 +264,13 @@ impl From<Sort> for (String, String) {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub enum Order {
+    #[default]
     Desc,
     Asc,
 }
 
-impl Default for Order {
-    fn default() -> Self {
-        Order::Desc
-    }
-}
-
 impl From<String> for Order {
     fn from(s: String) -> Self {
         match s.as_str() {
@@ -223,7 +289,7 @@ impl From<Order> for String {
     }
 }
 
-#[derive(Debug, Serialize, PartialEq)]
+#[derive(Debug, Serialize, PartialEq, Clone)]
 pub struct PostContent {
     pub name: String,
     pub full_name: String,
@@ -272,10 +338,13 @@ pub struct NewPostCreated {
 #[derive(Debug, Serialize)]
 pub struct EditedPost {
     pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub body_md: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub tags: Option<Vec<String>>,
     pub category: Option<String>,
     pub wip: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub message: Option<String>,
     pub original_revision: Option<OriginalRevisionPost>,
 }
@@ -0,0 +1,14 @@
+use chrono::{prelude::Local, DateTime};
+use serde::Deserialize;
+
+/// The user of the access token
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub id: i32,
+    pub name: String,
+    pub screen_name: String,
+    pub created_at: DateTime<Local>,
+    pub updated_at: DateTime<Local>,
+    pub icon: String,
+    pub email: String,
+}
@@ -0,0 +1,205 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use sha2::{Digest, Sha256};
+
+use esa::Esa;
+
+/// Folder of downloaded attachments next to the exported files
+pub const ASSETS_DIR: &str = "assets";
+/// URLs of the downloaded files. Hidden not to be taken as a post in a pulled directory
+const INDEX_FILE: &str = ".index.json";
+
+/// Attachments downloaded into `assets/` with content-hash names
+pub struct Assets {
+    dir: PathBuf,
+    /// File names by URL
+    index: BTreeMap<String, String>,
+}
+
+impl Assets {
+    /// Opens `assets/` in the directory. It does not have to exist
+    pub fn open(root: &Path) -> Result<Self> {
+        let dir = root.join(ASSETS_DIR);
+        let path = dir.join(INDEX_FILE);
+        let index = if path.exists() {
+            let index = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            serde_json::from_str(&index).with_context(|| format!("invalid {}", path.display()))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self { dir, index })
+    }
+
+    /// Whether attachments have been downloaded into the directory
+    pub fn exists(root: &Path) -> bool {
+        root.join(ASSETS_DIR).join(INDEX_FILE).exists()
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {}", self.dir.display()))?;
+        let path = self.dir.join(INDEX_FILE);
+        fs::write(&path, serde_json::to_string_pretty(&self.index)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Downloads attachments in the text not downloaded yet and returns whether any is downloaded.
+    /// A file failed to download is left linked to esa
+    pub async fn fetch(&mut self, esa: &Esa, text: &str) -> Result<bool> {
+        let mut downloaded = false;
+        for url in urls(text, &esa.team_id().to_string()) {
+            let exists = self
+                .index
+                .get(&url)
+                .is_some_and(|name| self.dir.join(name).exists());
+            if exists {
+                continue;
+            }
+            let content = match esa.download_attachment(&url).await {
+                Ok(content) => content,
+                Err(e) => {
+                    eprintln!("failed to download {}: {}", url, e);
+                    continue;
+                }
+            };
+            let name = file_name(&url, &content);
+            fs::create_dir_all(&self.dir)
+                .with_context(|| format!("failed to create {}", self.dir.display()))?;
+            let path = self.dir.join(&name);
+            fs::write(&path, content)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            self.index.insert(url, name);
+            downloaded = true;
+        }
+        // 中断しても取得済みのファイルを再取得しないように都度書き込む
+        if downloaded {
+            self.save()?;
+        }
+        Ok(downloaded)
+    }
+
+    /// Rewrites links to the downloaded attachments into the local copies
+    /// # Args
+    /// - root: Relative path from the file containing the text to the directory, like `../`
+    pub fn relink(&self, text: &str, root: &str) -> String {
+        let mut text = text.to_string();
+        // 長い URL から置き換えて、前方一致する短い URL に食われないようにする
+        let mut index = self.index.iter().collect::<Vec<_>>();
+        index.sort_by_key(|(url, _)| std::cmp::Reverse(url.len()));
+        for (url, name) in index {
+            text = text.replace(url.as_str(), &format!("{}{}/{}", root, ASSETS_DIR, name));
+        }
+        text
+    }
+
+    /// Rewrites links to the local copies back into the URLs on esa
+    pub fn unlink(&self, text: &str, root: &str) -> String {
+        let mut text = text.to_string();
+        for (url, name) in &self.index {
+            text = text.replace(&format!("{}{}/{}", root, ASSETS_DIR, name), url);
+        }
+        text
+    }
+}
+
+/// URLs of files attached to posts of the team
+pub fn urls(text: &str, team: &str) -> Vec<String> {
+    let pattern = Regex::new(&format!(
+        r#"https://(?:img\.esa\.io|files\.esa\.io|{}\.esa\.io|esa-storage[\w.-]*\.amazonaws\.com)/uploads/[^\s"'<>()\[\]]+"#,
+        regex::escape(team)
+    ))
+    .expect("invalid attachment pattern");
+    let mut urls = pattern
+        .find_iter(text)
+        .map(|m| m.as_str().to_string())
+        .collect::<Vec<_>>();
+    urls.sort();
+    urls.dedup();
+    urls
+}
+
+/// Relative path from the file at `path` to the top of the directory
+pub fn root_of(path: &str) -> String {
+    "../".repeat(path.matches('/').count())
+}
+
+/// Name from the hash of the content with the extension in the URL
+fn file_name(url: &str, content: &[u8]) -> String {
+    let hash = Sha256::digest(content)
+        .iter()
+        .take(16)
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    let path = url.split(['?', '#']).next().unwrap_or(url);
+    let file = path.rsplit('/').next().unwrap_or(path);
+    match file.rsplit_once('.') {
+        Some((_, extension))
+            if !extension.is_empty()
+                && extension.len() <= 5
+                && extension.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            format!("{}.{}", hash, extension.to_lowercase())
+        }
+        _ => hash,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls() {
+        let text = "![a](https://img.esa.io/uploads/production/attachments/1/a.png) \
+            [b](https://docs.esa.io/uploads/b.pdf) <img src=\"https://files.esa.io/uploads/c.jpg\"> \
+            https://other.esa.io/uploads/d.png https://img.esa.io/uploads/production/attachments/1/a.png";
+        assert_eq!(
+            vec![
+                "https://docs.esa.io/uploads/b.pdf",
+                "https://files.esa.io/uploads/c.jpg",
+                "https://img.esa.io/uploads/production/attachments/1/a.png",
+            ],
+            urls(text, "docs")
+        );
+    }
+
+    #[test]
+    fn test_relink() {
+        let assets = Assets {
+            dir: PathBuf::from(ASSETS_DIR),
+            index: [
+                ("https://img.esa.io/uploads/a.png", "0123.png"),
+                ("https://img.esa.io/uploads/a.png.jpg", "4567.jpg"),
+            ]
+            .into_iter()
+            .map(|(url, name)| (url.to_string(), name.to_string()))
+            .collect(),
+        };
+        let text =
+            "![a](https://img.esa.io/uploads/a.png) ![b](https://img.esa.io/uploads/a.png.jpg)";
+        let relinked = assets.relink(text, "../");
+        assert_eq!(
+            "![a](../assets/0123.png) ![b](../assets/4567.jpg)",
+            relinked
+        );
+        assert_eq!(text, assets.unlink(&relinked, "../"));
+        assert_eq!("../", root_of("手順書/デプロイ.md"));
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e.png",
+            file_name("https://img.esa.io/uploads/a.PNG?x=1", b"hello")
+        );
+        assert_eq!(
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e",
+            file_name("https://img.esa.io/uploads/a", b"hello")
+        );
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::{bail, Context, Result};
+use chrono::{prelude::Local, DateTime};
+use serde::{Deserialize, Serialize};
+
+use esa::{
+    post::{Include, Order, Post, SearchQuery, Sort},
+    Esa,
+};
+
+use super::assets::{self, Assets};
+use super::cache;
+use super::config::Env;
+use super::paging::Pages;
+use super::rate_limit;
+use super::workspace;
+
+/// Manifest file in a backup
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+const ARCHIVE_EXTENSION: &str = ".tar.zst";
+/// zstd の圧縮レベル。速度とサイズの釣り合いを取る
+const COMPRESSION_LEVEL: i32 = 9;
+
+/// What is stored in a backup
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    pub team: String,
+    /// When the first backup started
+    pub created_at: DateTime<Local>,
+    /// When the last backup started
+    pub updated_at: DateTime<Local>,
+    pub posts: Vec<Entry>,
+}
+
+/// A post in a backup
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Entry {
+    pub number: i32,
+    pub full_name: String,
+    pub revision_number: i32,
+    pub updated_at: DateTime<Local>,
+    pub comments_count: i32,
+    #[serde(default)]
+    pub stargazers_count: i32,
+    /// When the post was found deleted on esa. The backed up files are kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Local>>,
+}
+
+impl Entry {
+    fn of(post: &Post) -> Self {
+        Self {
+            number: post.number,
+            full_name: post.full_name.clone(),
+            revision_number: post.revision_number,
+            updated_at: post.updated_at,
+            comments_count: post.comments_count,
+            stargazers_count: post.stargazers_count,
+            deleted_at: None,
+        }
+    }
+}
+
+/// Backup in a directory or a `.tar.zst` archive.
+/// An archive is extracted into a temporary directory while it is used
+pub struct Backup {
+    target: PathBuf,
+    dir: PathBuf,
+    archive: bool,
+}
+
+impl Backup {
+    /// Opens the backup. It does not have to exist
+    pub fn open(target: &Path) -> Result<Self> {
+        let archive = target.to_string_lossy().ends_with(ARCHIVE_EXTENSION);
+        if !archive {
+            return Ok(Self {
+                target: target.to_path_buf(),
+                dir: target.to_path_buf(),
+                archive,
+            });
+        }
+
+        let dir = env::temp_dir().join(format!("esa-cli-backup-{}", process::id()));
+        if dir.exists() {
+            fs::remove_dir_all(&dir)?;
+        }
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let backup = Self {
+            target: target.to_path_buf(),
+            dir,
+            archive,
+        };
+        if target.exists() {
+            let file = File::open(target)
+                .with_context(|| format!("failed to open {}", target.display()))?;
+            tar::Archive::new(zstd::Decoder::new(file)?)
+                .unpack(&backup.dir)
+                .with_context(|| format!("failed to extract {}", target.display()))?;
+        }
+        Ok(backup)
+    }
+
+    pub fn manifest(&self) -> Result<Option<Manifest>> {
+        let path = self.dir.join(MANIFEST_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let manifest = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Ok(Some(serde_json::from_str(&manifest).with_context(
+            || format!("invalid manifest in {}", self.target.display()),
+        )?))
+    }
+
+    fn post_path(&self, number: i32, extension: &str) -> PathBuf {
+        self.dir
+            .join("posts")
+            .join(format!("{}.{}", number, extension))
+    }
+
+    /// Post as returned from the API with comments and stargazers
+    pub fn read_post(&self, number: i32) -> Result<Post> {
+        let path = self.post_path(number, "json");
+        let post = fs::read_to_string(&path)
+            .with_context(|| format!("#{} is not found in the backup", number))?;
+        serde_json::from_str(&post).with_context(|| format!("invalid post {}", path.display()))
+    }
+
+    /// Writes the post as JSON and Markdown with front matter.
+    /// Only Markdown links to the downloaded attachments to keep JSON as returned from the API
+    fn write_post(&self, post: &Post, assets: &Assets) -> Result<()> {
+        let json = self.post_path(post.number, "json");
+        if let Some(dir) = json.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&json, serde_json::to_string_pretty(post)?)
+            .with_context(|| format!("failed to write {}", json.display()))?;
+        let markdown = self.post_path(post.number, "md");
+        let content = assets.relink(&workspace::format_post(post), &assets::root_of("posts/"));
+        fs::write(&markdown, content)
+            .with_context(|| format!("failed to write {}", markdown.display()))
+    }
+
+    fn write_manifest(&self, manifest: &mut Manifest) -> Result<()> {
+        manifest.posts.sort_by_key(|entry| entry.number);
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(MANIFEST_FILE);
+        fs::write(&path, serde_json::to_string_pretty(manifest)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Packs the archive. Nothing to do for a directory
+    fn finish(&self) -> Result<()> {
+        if !self.archive {
+            return Ok(());
+        }
+        // 書き込み途中で失敗しても元のアーカイブを壊さないように別名で作って置き換える
+        let tmp = self.target.with_extension("zst.tmp");
+        let file =
+            File::create(&tmp).with_context(|| format!("failed to create {}", tmp.display()))?;
+        let mut builder = tar::Builder::new(zstd::Encoder::new(file, COMPRESSION_LEVEL)?);
+        builder.append_dir_all(".", &self.dir)?;
+        builder.into_inner()?.finish()?;
+        fs::rename(&tmp, &self.target)
+            .with_context(|| format!("failed to write {}", self.target.display()))
+    }
+}
+
+impl Drop for Backup {
+    fn drop(&mut self) {
+        if self.archive {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+/// Back up all posts including WIP with comments and stargazers
+/// # Args
+/// - target: Directory or `.tar.zst` file
+/// - full: Fetches all posts even if the backup exists
+/// - attachments: Downloads attached files into `assets/`.
+///   Always done once the backup has them
+pub async fn backup(
+    esa: &Esa,
+    esa_env: &Env,
+    target: &Path,
+    full: bool,
+    attachments: bool,
+) -> Result<()> {
+    let backup = Backup::open(target)?;
+    let attachments = attachments || Assets::exists(&backup.dir);
+    let mut assets = Assets::open(&backup.dir)?;
+    let started_at = Local::now();
+    let mut manifest = match backup.manifest()? {
+        Some(manifest) if manifest.team != esa.team_id().to_string() => bail!(
+            "{} is a backup of team '{}'",
+            target.display(),
+            manifest.team
+        ),
+        Some(manifest) => manifest,
+        None => Manifest {
+            team: esa.team_id().to_string(),
+            created_at: started_at,
+            updated_at: started_at,
+            posts: Vec::new(),
+        },
+    };
+    let include = vec![
+        Include::Comments,
+        Include::CommentStargazers,
+        Include::Stargazers,
+    ];
+    // コメントやスターが増えても更新日は変わらないので、差分取得では一覧を安く取得して比べ、
+    // 変わった記事だけコメントとスター付きで取り直す
+    let incremental = !full && !manifest.posts.is_empty();
+    let search_query = SearchQuery::new(
+        None,
+        (!incremental).then(|| include.clone()),
+        Some(Sort::Number(Order::Asc)),
+    );
+
+    let (mut fetched, mut changed) = (0, 0);
+    let mut numbers = HashSet::new();
+    let mut pages = Pages::new(search_query).with_progress();
+    while let Some(page) = pages.next(esa).await? {
+        cache::remember(esa_env, &manifest.team, &page);
+        for post in &page {
+            fetched += 1;
+            numbers.insert(post.number);
+            let entry = Entry::of(post);
+            // 添付ファイルを新たに取得した記事は Markdown のリンクを書き換えるため書き直す
+            let downloaded = attachments && assets.fetch(esa, &post.body_md).await?;
+            if manifest.posts.contains(&entry) && !downloaded {
+                continue;
+            }
+            if incremental {
+                let post =
+                    rate_limit::write(esa, || esa.post_with(post.number, include.clone())).await?;
+                backup.write_post(&post, &assets)?;
+            } else {
+                backup.write_post(post, &assets)?;
+            }
+            manifest.posts.retain(|e| e.number != post.number);
+            manifest.posts.push(entry);
+            changed += 1;
+        }
+        // 中断しても取得済みの記事を manifest に残す
+        backup.write_manifest(&mut manifest)?;
+    }
+
+    // 一覧に無い記事は esa で削除された
+    let mut deleted = 0;
+    for entry in &mut manifest.posts {
+        if entry.deleted_at.is_none() && !numbers.contains(&entry.number) {
+            entry.deleted_at = Some(started_at);
+            deleted += 1;
+        }
+    }
+    manifest.updated_at = started_at;
+    backup.write_manifest(&mut manifest)?;
+    backup.finish()?;
+    println!(
+        "backed up {} posts into {}: {} fetched, {} changed, {} deleted",
+        manifest.posts.len(),
+        target.display(),
+        fetched,
+        changed,
+        deleted
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_archive_round_trip() {
+        let dir = env::temp_dir().join(format!("esa-cli-backup-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let target = dir.join("backup.tar.zst");
+        let now = Local::now();
+        let mut manifest = Manifest {
+            team: String::from("docs"),
+            created_at: now,
+            updated_at: now,
+            posts: vec![Entry {
+                number: 1,
+                full_name: String::from("手順書/デプロイ"),
+                revision_number: 3,
+                updated_at: now,
+                comments_count: 2,
+                stargazers_count: 1,
+                deleted_at: Some(now),
+            }],
+        };
+
+        let backup = Backup::open(&target).unwrap();
+        assert_eq!(None, backup.manifest().unwrap());
+        backup.write_manifest(&mut manifest).unwrap();
+        backup.finish().unwrap();
+        drop(backup);
+
+        let backup = Backup::open(&target).unwrap();
+        assert_eq!(Some(manifest), backup.manifest().unwrap());
+        drop(backup);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
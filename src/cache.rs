@@ -0,0 +1,324 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use chrono::{prelude::Local, DateTime};
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+
+use esa::{
+    post::{Order, Post, SearchQuery, Sort},
+    Esa,
+};
+
+use super::config::Env;
+use super::paging::{self, Pages};
+
+/// Length of n-grams indexed for full-text search.
+/// 日本語は単語の区切りがないので形態素解析の代わりに 2-gram で引く
+const GRAM_LENGTH: usize = 2;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS posts (
+    number INTEGER PRIMARY KEY,
+    updated_at TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS grams (
+    gram TEXT NOT NULL,
+    number INTEGER NOT NULL,
+    PRIMARY KEY (gram, number)
+) WITHOUT ROWID;
+CREATE INDEX IF NOT EXISTS grams_number ON grams (number);
+CREATE TABLE IF NOT EXISTS meta (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+/// Posts of a team kept on disk to search without the network
+pub struct PostCache {
+    connection: Connection,
+}
+
+impl PostCache {
+    fn path(esa_env: &Env, team_id: &str) -> PathBuf {
+        esa_env.cache_dir_path.join(team_id).join("posts.sqlite3")
+    }
+
+    pub fn open(esa_env: &Env, team_id: &str) -> Result<Self> {
+        let path = Self::path(esa_env, team_id);
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let connection = Connection::open(&path)
+            .with_context(|| format!("failed to open {}", path.display()))?;
+        Self::init(connection)
+    }
+
+    fn init(connection: Connection) -> Result<Self> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// Adds or replaces the posts
+    pub fn store(&mut self, posts: &[Post]) -> Result<()> {
+        let transaction = self.connection.transaction()?;
+        for post in posts {
+            transaction.execute(
+                "INSERT OR REPLACE INTO posts (number, updated_at, json) VALUES (?1, ?2, ?3)",
+                params![
+                    post.number,
+                    post.updated_at.to_rfc3339(),
+                    serde_json::to_string(post)?
+                ],
+            )?;
+            transaction.execute("DELETE FROM grams WHERE number = ?1", params![post.number])?;
+            let mut insert =
+                transaction.prepare_cached("INSERT INTO grams (gram, number) VALUES (?1, ?2)")?;
+            for gram in ngrams(&searchable_text(post)) {
+                insert.execute(params![gram, post.number])?;
+            }
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Removes posts not in `numbers`
+    fn retain(&mut self, numbers: &HashSet<i32>) -> Result<usize> {
+        let transaction = self.connection.transaction()?;
+        let cached = transaction
+            .prepare("SELECT number FROM posts")?
+            .query_map([], |row| row.get::<_, i32>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        let mut removed = 0;
+        for number in cached.into_iter().filter(|n| !numbers.contains(n)) {
+            transaction.execute("DELETE FROM posts WHERE number = ?1", params![number])?;
+            transaction.execute("DELETE FROM grams WHERE number = ?1", params![number])?;
+            removed += 1;
+        }
+        transaction.commit()?;
+        Ok(removed)
+    }
+
+    /// Posts containing all words in the query, recently updated first
+    pub fn search(&self, query: &str) -> Result<Vec<Post>> {
+        let terms = query.split_whitespace().map(normalize).collect::<Vec<_>>();
+        let grams = terms
+            .iter()
+            .flat_map(|term| ngrams(term))
+            .collect::<BTreeSet<_>>();
+
+        // n-gram で候補を絞ってから本文に含まれるかを確かめる
+        let sql = if grams.is_empty() {
+            String::from("SELECT json FROM posts ORDER BY updated_at DESC")
+        } else {
+            format!(
+                "SELECT json FROM posts WHERE number IN (
+                    SELECT number FROM grams WHERE gram IN ({})
+                    GROUP BY number HAVING COUNT(*) = {}
+                ) ORDER BY updated_at DESC",
+                vec!["?"; grams.len()].join(", "),
+                grams.len()
+            )
+        };
+        let mut statement = self.connection.prepare(&sql)?;
+        let rows = statement.query_map(params_from_iter(grams.iter()), |row| {
+            row.get::<_, String>(0)
+        })?;
+        let mut posts = Vec::new();
+        for json in rows {
+            let post: Post = serde_json::from_str(&json?)?;
+            let text = normalize(&searchable_text(&post));
+            if terms.iter().all(|term| text.contains(term.as_str())) {
+                posts.push(post);
+            }
+        }
+        Ok(posts)
+    }
+
+    pub fn len(&self) -> Result<usize> {
+        let count: i64 = self
+            .connection
+            .query_row("SELECT COUNT(*) FROM posts", [], |row| row.get(0))?;
+        Ok(count as usize)
+    }
+
+    /// When the last sync started
+    fn synced_at(&self) -> Result<Option<DateTime<Local>>> {
+        let synced_at: Option<String> = self
+            .connection
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'synced_at'",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(synced_at
+            .and_then(|s| DateTime::parse_from_rfc3339(&s).ok())
+            .map(|d| d.with_timezone(&Local)))
+    }
+
+    fn set_synced_at(&self, synced_at: DateTime<Local>) -> Result<()> {
+        self.connection.execute(
+            "INSERT OR REPLACE INTO meta (key, value) VALUES ('synced_at', ?1)",
+            params![synced_at.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+}
+
+/// Keeps the fetched posts in the cache.
+/// Failing to write the cache does not fail the command
+pub fn remember(esa_env: &Env, team_id: &str, posts: &[Post]) {
+    let result = PostCache::open(esa_env, team_id).and_then(|mut cache| cache.store(posts));
+    if let Err(e) = result {
+        log::warn!("failed to cache posts: {:#}", e);
+    }
+}
+
+/// Fetch posts updated since the last sync into the cache
+/// # Args
+/// - full: Fetches all posts and removes deleted posts from the cache
+pub async fn sync(esa: &Esa, esa_env: &Env, full: bool) -> Result<()> {
+    let mut cache = PostCache::open(esa_env, &esa.team_id().to_string())?;
+    let started_at = Local::now();
+    let query = match cache.synced_at()? {
+        Some(synced_at) if !full => Some(paging::updated_since(synced_at)),
+        _ => None,
+    };
+
+    let mut numbers = HashSet::new();
+    let search_query = SearchQuery::new(query.clone(), None, Some(Sort::Number(Order::Asc)));
+    let mut pages = Pages::new(search_query).with_progress();
+    while let Some(page) = pages.next(esa).await? {
+        cache.store(&page)?;
+        numbers.extend(page.iter().map(|post| post.number));
+    }
+    // 差分取得では削除された記事が分からないので全件取得したときだけ消す
+    let removed = if query.is_none() {
+        cache.retain(&numbers)?
+    } else {
+        0
+    };
+    cache.set_synced_at(started_at)?;
+
+    println!(
+        "synced {} posts: {} fetched, {} removed",
+        cache.len()?,
+        numbers.len(),
+        removed
+    );
+    Ok(())
+}
+
+/// Text searched in a post
+fn searchable_text(post: &Post) -> String {
+    format!(
+        "{}\n{}\n{}",
+        post.full_name,
+        post.tags.join(" "),
+        post.body_md
+    )
+}
+
+/// Folds letter case and full-width alphanumerics to search them alike
+fn normalize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            // 全角英数記号を半角に寄せる
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            '\u{3000}' => ' ',
+            c => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Distinct n-grams of the normalized text not spanning whitespace
+fn ngrams(text: &str) -> BTreeSet<String> {
+    let chars = normalize(text).chars().collect::<Vec<_>>();
+    chars
+        .windows(GRAM_LENGTH)
+        .filter(|gram| !gram.iter().any(|c| c.is_whitespace()))
+        .map(|gram| gram.iter().collect())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post(number: i32, full_name: &str, body_md: &str) -> Post {
+        let json = serde_json::json!({
+            "number": number,
+            "name": full_name,
+            "full_name": full_name,
+            "wip": false,
+            "body_md": body_md,
+            "body_html": "",
+            "created_at": "2021-04-01T10:00:00+09:00",
+            "message": "",
+            "url": "",
+            "updated_at": format!("2021-04-{:02}T10:00:00+09:00", number),
+            "tags": ["ops"],
+            "category": null,
+            "revision_number": 1,
+            "created_by": {"myself": true, "name": "", "screen_name": "koizr", "icon": ""},
+            "updated_by": {"myself": true, "name": "", "screen_name": "koizr", "icon": ""},
+            "kind": "stock",
+            "comments_count": 0,
+            "tasks_count": 0,
+            "done_tasks_count": 0,
+            "stargazers_count": 0,
+            "watchers_count": 0,
+            "star": false,
+            "watch": false
+        });
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_ngrams() {
+        assert_eq!(
+            ["デプ", "プロ", "ロイ", "a1"]
+                .into_iter()
+                .map(String::from)
+                .collect::<BTreeSet<_>>(),
+            ngrams("デプロイ Ａ1")
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let mut cache = PostCache::init(Connection::open_in_memory().unwrap()).unwrap();
+        cache
+            .store(&[
+                post(1, "手順書/デプロイ", "本番環境にデプロイする"),
+                post(2, "手順書/ロールバック", "Deploy を取り消す"),
+                post(3, "日報", "プロイセンの歴史"),
+            ])
+            .unwrap();
+        let numbers = |cache: &PostCache, query: &str| {
+            cache
+                .search(query)
+                .unwrap()
+                .iter()
+                .map(|post| post.number)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![1], numbers(&cache, "デプロイ"));
+        assert_eq!(vec![2], numbers(&cache, "ｄｅｐｌｏｙ"));
+        assert_eq!(vec![1], numbers(&cache, "本番 デプロイ"));
+        assert_eq!(vec![3, 2, 1], numbers(&cache, "ops"));
+        assert_eq!(Vec::<i32>::new(), numbers(&cache, "デプロイセン"));
+
+        cache
+            .store(&[post(1, "手順書/デプロイ", "削除済み")])
+            .unwrap();
+        assert_eq!(Vec::<i32>::new(), numbers(&cache, "本番"));
+        cache.retain(&[2].into_iter().collect()).unwrap();
+        assert_eq!(1, cache.len().unwrap());
+    }
+}
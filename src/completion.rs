@@ -0,0 +1,552 @@
+use std::collections::BTreeSet;
+use std::fs::{self, File};
+use std::io::{BufReader, Write};
+use std::path::PathBuf;
+
+use anyhow::Result;
+use chrono::{prelude::Local, DateTime, Duration};
+use clap::{Command, ValueEnum};
+use clap_complete::Shell;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_reader, to_writer};
+
+use esa::Esa;
+
+use super::config::{Config, Env, TeamConfig};
+use super::paging::Pages;
+
+const BIN_NAME: &str = "esa-cli";
+
+/// Name of the hidden subcommand that prints dynamic candidates
+pub const COMPLETE_SUBCOMMAND: &str = "complete-candidates";
+
+/// How long fetched candidates are reused
+const CACHE_TTL_MINUTES: i64 = 5;
+
+/// Number of pages of recently updated posts fetched as candidates
+const CACHE_PAGES: i32 = 3;
+
+/// Candidates which are resolved when completing
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Kind {
+    Teams,
+    Posts,
+    Categories,
+    Tags,
+    /// `+<tag>` arguments of `post tag`
+    AddTags,
+    /// `in:<category>` and `tag:<tag>` search terms
+    Query,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Teams => "teams",
+            Kind::Posts => "posts",
+            Kind::Categories => "categories",
+            Kind::Tags => "tags",
+            Kind::AddTags => "add-tags",
+            Kind::Query => "query",
+        }
+    }
+}
+
+/// Argument completed with dynamic candidates
+struct DynamicArg {
+    /// Subcommand path like `post new`. `None` means a global option
+    subcommand: Option<&'static str>,
+    /// Empty means the positional argument of the subcommand
+    flags: &'static [&'static str],
+    kind: Kind,
+}
+
+const DYNAMIC_ARGS: &[DynamicArg] = &[
+    DynamicArg {
+        subcommand: None,
+        flags: &["-t", "--team"],
+        kind: Kind::Teams,
+    },
+    DynamicArg {
+        subcommand: Some("post"),
+        flags: &[],
+        kind: Kind::Posts,
+    },
+    DynamicArg {
+        subcommand: Some("post"),
+        flags: &["-q", "--query"],
+        kind: Kind::Query,
+    },
+    DynamicArg {
+        subcommand: Some("post new"),
+        flags: &["--category"],
+        kind: Kind::Categories,
+    },
+    DynamicArg {
+        subcommand: Some("post new"),
+        flags: &["--tag"],
+        kind: Kind::Tags,
+    },
+    DynamicArg {
+        subcommand: Some("post mv"),
+        flags: &[],
+        kind: Kind::Categories,
+    },
+    DynamicArg {
+        subcommand: Some("post tag"),
+        flags: &[],
+        kind: Kind::AddTags,
+    },
+    DynamicArg {
+        subcommand: Some("post tag"),
+        flags: &["-r", "--remove"],
+        kind: Kind::Tags,
+    },
+    DynamicArg {
+        subcommand: Some("pull"),
+        flags: &["-c", "--category"],
+        kind: Kind::Categories,
+    },
+];
+
+/// Writes the completion script for `shell`.
+/// bash, zsh and fish scripts call `esa-cli complete-candidates` for dynamic candidates.
+pub fn generate(shell: Shell, cmd: &mut Command, out: &mut dyn Write) -> Result<()> {
+    let mut script = Vec::new();
+    clap_complete::generate(shell, cmd, BIN_NAME, &mut script);
+    let script = String::from_utf8(script)?;
+
+    let script = match shell {
+        Shell::Bash => format!("{}\n{}", script, bash_script(cmd)),
+        Shell::Zsh => zsh_script(&script, cmd),
+        Shell::Fish => format!("{}\n{}", script, fish_script()),
+        _ => script,
+    };
+    out.write_all(script.as_bytes())?;
+    Ok(())
+}
+
+fn subcommand_names(cmd: &Command) -> Vec<String> {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set())
+        .map(|sub| sub.get_name().to_string())
+        .collect()
+}
+
+/// Nested subcommands like `post new` as `case` patterns
+fn nested_subcommands(cmd: &Command) -> String {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set())
+        .flat_map(|sub| {
+            sub.get_subcommands()
+                .filter(|nested| !nested.is_hide_set())
+                .map(move |nested| format!("\"{} {}\"", sub.get_name(), nested.get_name()))
+        })
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// Subcommand at the path like `post new`
+fn find_subcommand<'a, 'b>(cmd: &'a Command<'b>, path: &str) -> Option<&'a Command<'b>> {
+    path.split(' ')
+        .try_fold(cmd, |cmd, name| cmd.find_subcommand(name))
+}
+
+/// Flags of `subcommand` which take a value, so that the next word is not a positional argument
+fn value_flags(cmd: &Command, subcommand: &str) -> Vec<String> {
+    find_subcommand(cmd, subcommand)
+        .into_iter()
+        .chain(std::iter::once(cmd))
+        .flat_map(|cmd| cmd.get_arguments())
+        .filter(|arg| arg.is_takes_value_set() && !arg.is_positional())
+        .flat_map(|arg| {
+            let short = arg.get_short().map(|s| format!("-{}", s));
+            let long = arg.get_long().map(|l| format!("--{}", l));
+            short.into_iter().chain(long)
+        })
+        .collect()
+}
+
+/// `case` patterns matching `<subcommand>:<previous word>`
+fn case_patterns(arg: &DynamicArg) -> String {
+    let subcommand = arg
+        .subcommand
+        .map_or_else(|| String::from("*"), |sub| format!("\"{}\"", sub));
+    arg.flags
+        .iter()
+        .map(|flag| format!("{}:{}", subcommand, flag))
+        .collect::<Vec<_>>()
+        .join("|")
+}
+
+/// `case` branches selecting the candidate kind from the previous word (bash and zsh)
+fn flag_cases() -> String {
+    DYNAMIC_ARGS
+        .iter()
+        .filter(|arg| !arg.flags.is_empty())
+        .map(|arg| {
+            format!(
+                "        {}) kind=\"{}\" ;;\n",
+                case_patterns(arg),
+                arg.kind.name()
+            )
+        })
+        .collect()
+}
+
+/// Checks selecting the candidate kind for positional arguments (bash and zsh)
+fn positional_checks(cmd: &Command) -> String {
+    DYNAMIC_ARGS
+        .iter()
+        .filter(|arg| arg.flags.is_empty())
+        .filter_map(|arg| arg.subcommand.map(|sub| (sub, arg.kind)))
+        .map(|(sub, kind)| {
+            format!(
+                "    if [[ -z \"${{kind}}\" && \"${{sub}}\" == \"{sub}\" && \"${{cur}}\" != -* && \" {flags} \" != *\" ${{prev}} \"* ]]; then\n        kind=\"{kind}\"\n    fi\n",
+                sub = sub,
+                flags = value_flags(cmd, sub).join(" "),
+                kind = kind.name(),
+            )
+        })
+        .collect()
+}
+
+fn bash_script(cmd: &Command) -> String {
+    let flag_cases = flag_cases();
+    let positional = positional_checks(cmd);
+
+    format!(
+        r#"_{bin}_dynamic() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    local sub="" team="" kind="" word before=""
+    for word in "${{COMP_WORDS[@]:1:COMP_CWORD-1}}"; do
+        case "${{before}}" in
+            -t|--team) team="${{word}}" ;;
+        esac
+        if [[ -z "${{sub}}" ]]; then
+            case "${{word}}" in
+                {subcommands}) sub="${{word}}" ;;
+            esac
+        elif [[ "${{sub}}" != *" "* ]]; then
+            case "${{sub}} ${{word}}" in
+                {nested}) sub="${{sub}} ${{word}}" ;;
+            esac
+        fi
+        before="${{word}}"
+    done
+    case "${{sub}}:${{prev}}" in
+{flag_cases}    esac
+{positional}    if [[ -n "${{kind}}" ]]; then
+        local IFS=$'\n'
+        COMPREPLY=($(compgen -W "$({bin} ${{team:+--team "${{team}}"}} {complete} "${{kind}}" 2>/dev/null | cut -f1)" -- "${{cur}}"))
+        return 0
+    fi
+    _{bin} "$@"
+}}
+
+complete -F _{bin}_dynamic -o bashdefault -o default {bin}
+"#,
+        bin = BIN_NAME,
+        complete = COMPLETE_SUBCOMMAND,
+        subcommands = subcommand_names(cmd).join("|"),
+        nested = nested_subcommands(cmd),
+        flag_cases = flag_cases,
+        positional = positional,
+    )
+}
+
+fn zsh_script(script: &str, cmd: &Command) -> String {
+    let flag_cases = flag_cases();
+    let positional = positional_checks(cmd);
+
+    let dynamic = format!(
+        r#"_{bin}_dynamic() {{
+    local cur="${{words[CURRENT]}}"
+    local prev="${{words[CURRENT-1]}}"
+    local sub="" team="" kind="" i
+    for ((i = 2; i < CURRENT; i++)); do
+        case "${{words[i-1]}}" in
+            -t|--team) team="${{words[i]}}" ;;
+        esac
+        if [[ -z "${{sub}}" ]]; then
+            case "${{words[i]}}" in
+                {subcommands}) sub="${{words[i]}}" ;;
+            esac
+        elif [[ "${{sub}}" != *" "* ]]; then
+            case "${{sub}} ${{words[i]}}" in
+                {nested}) sub="${{sub}} ${{words[i]}}" ;;
+            esac
+        fi
+    done
+    case "${{sub}}:${{prev}}" in
+{flag_cases}    esac
+{positional}    if [[ -n "${{kind}}" ]]; then
+        local -a candidates
+        candidates=("${{(@f)$({bin} ${{team:+--team "${{team}}"}} {complete} "${{kind}}" 2>/dev/null | sed -e 's/:/\\:/g' | tr '\t' ':')}}")
+        _describe -t "${{kind}}" "${{kind}}" candidates
+        return
+    fi
+    _{bin} "$@"
+}}
+
+"#,
+        bin = BIN_NAME,
+        complete = COMPLETE_SUBCOMMAND,
+        subcommands = subcommand_names(cmd).join("|"),
+        nested = nested_subcommands(cmd),
+        flag_cases = flag_cases,
+        positional = positional,
+    );
+
+    // 生成されたスクリプト末尾の呼び出しを dynamic 版に差し替える
+    let trailer = format!("\n_{} \"$@\"", BIN_NAME);
+    let body = match script.rfind(&trailer) {
+        Some(index) => &script[..index + 1],
+        None => script,
+    };
+    format!(
+        "{body}\n{dynamic}compdef _{bin}_dynamic {bin}\nif [[ \"${{funcstack[1]}}\" == \"_{bin}\" ]]; then\n    _{bin}_dynamic \"$@\"\nfi\n",
+        body = body,
+        dynamic = dynamic,
+        bin = BIN_NAME,
+    )
+}
+
+fn fish_script() -> String {
+    let completions = DYNAMIC_ARGS
+        .iter()
+        .map(|arg| {
+            let condition = arg
+                .subcommand
+                .map(|sub| {
+                    let seen = sub
+                        .split(' ')
+                        .map(|name| format!("__fish_seen_subcommand_from {}", name))
+                        .collect::<Vec<_>>()
+                        .join("; and ");
+                    format!(" -n '{}'", seen)
+                })
+                .unwrap_or_default();
+            let flags = arg
+                .flags
+                .iter()
+                .map(|flag| match flag.strip_prefix("--") {
+                    Some(long) => format!(" -l {}", long),
+                    None => format!(" -s {}", flag.trim_start_matches('-')),
+                })
+                .collect::<String>();
+            format!(
+                "complete -c {bin}{condition}{flags} -f -a '(__{fn_bin}_complete {kind})'\n",
+                bin = BIN_NAME,
+                fn_bin = BIN_NAME.replace('-', "_"),
+                condition = condition,
+                flags = flags,
+                kind = arg.kind.name(),
+            )
+        })
+        .collect::<String>();
+
+    format!(
+        r#"function __{fn_bin}_complete
+    set -l tokens (commandline -opc)
+    set -l team
+    for i in (seq 2 (count $tokens))
+        if contains -- $tokens[(math $i - 1)] -t --team
+            set team --team $tokens[$i]
+        end
+    end
+    {bin} $team {complete} $argv 2>/dev/null
+end
+
+{completions}"#,
+        fn_bin = BIN_NAME.replace('-', "_"),
+        bin = BIN_NAME,
+        complete = COMPLETE_SUBCOMMAND,
+        completions = completions,
+    )
+}
+
+/// Prints candidates line by line. A description follows a tab if exists.
+pub async fn print_candidates(
+    kind: Kind,
+    esa_env: &Env,
+    config: &Config,
+    team: Option<&TeamConfig>,
+) -> Result<()> {
+    let candidates = match kind {
+        Kind::Teams => config
+            .teams()
+            .map(|team| team.id.to_string())
+            .collect::<Vec<_>>(),
+        _ => {
+            let team = match team {
+                Some(team) => team,
+                None => return Ok(()),
+            };
+            let cache = CompletionCache::load_or_fetch(esa_env, team).await?;
+            match kind {
+                Kind::Posts => cache
+                    .posts
+                    .iter()
+                    .map(|post| format!("{}\t{}", post.number, post.full_name))
+                    .collect(),
+                Kind::Categories => cache.categories().into_iter().collect(),
+                Kind::Tags => cache.tags().into_iter().collect(),
+                Kind::AddTags => cache
+                    .tags()
+                    .into_iter()
+                    .map(|tag| format!("+{}", tag))
+                    .collect(),
+                Kind::Query => cache
+                    .categories()
+                    .into_iter()
+                    .map(|category| format!("in:{}", category))
+                    .chain(cache.tags().into_iter().map(|tag| format!("tag:{}", tag)))
+                    .collect(),
+                Kind::Teams => unreachable!(),
+            }
+        }
+    };
+    for candidate in candidates {
+        println!("{}", candidate);
+    }
+    Ok(())
+}
+
+/// Recently updated posts kept for a short time to complete quickly
+#[derive(Debug, Serialize, Deserialize)]
+struct CompletionCache {
+    fetched_at: DateTime<Local>,
+    posts: Vec<CachedPost>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedPost {
+    number: i32,
+    full_name: String,
+    category: Option<String>,
+    tags: Vec<String>,
+}
+
+impl CompletionCache {
+    fn path(esa_env: &Env, team: &TeamConfig) -> PathBuf {
+        let mut path = esa_env.cache_dir_path.clone();
+        path.push(team.id.to_string());
+        path.push("completion.json");
+        path
+    }
+
+    async fn load_or_fetch(esa_env: &Env, team: &TeamConfig) -> Result<Self> {
+        let path = Self::path(esa_env, team);
+        if let Some(cache) = Self::load(&path) {
+            if Local::now() - cache.fetched_at < Duration::minutes(CACHE_TTL_MINUTES) {
+                return Ok(cache);
+            }
+        }
+
+        // TAB を押すたびにトークンのコマンドを実行しないように、取得するときだけ読む
+        let esa = Esa::new(team.resolve()?);
+        let mut posts = Vec::new();
+        let mut pages = Pages::new(esa::post::SearchQuery::new(None, None, None));
+        for _ in 0..CACHE_PAGES {
+            let page = match pages.next(&esa).await? {
+                Some(page) => page,
+                None => break,
+            };
+            posts.extend(page.into_iter().map(|post| CachedPost {
+                number: post.number,
+                full_name: post.full_name,
+                category: post.category,
+                tags: post.tags,
+            }));
+        }
+        let cache = Self {
+            fetched_at: Local::now(),
+            posts,
+        };
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        to_writer(File::create(&path)?, &cache)?;
+        Ok(cache)
+    }
+
+    fn load(path: &PathBuf) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        from_reader(BufReader::new(file)).ok()
+    }
+
+    /// Categories including their parents
+    fn categories(&self) -> BTreeSet<String> {
+        self.posts
+            .iter()
+            .filter_map(|post| post.category.as_deref())
+            .flat_map(|category| {
+                let parts = category.split('/').collect::<Vec<_>>();
+                (1..=parts.len()).map(move |i| parts[..i].join("/"))
+            })
+            .collect()
+    }
+
+    fn tags(&self) -> BTreeSet<String> {
+        self.posts
+            .iter()
+            .flat_map(|post| post.tags.iter().cloned())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nested_subcommands() {
+        let cmd = Command::new(BIN_NAME).subcommand(
+            Command::new("post")
+                .subcommand(
+                    Command::new("new").arg(
+                        clap::Arg::new("category")
+                            .long("category")
+                            .takes_value(true),
+                    ),
+                )
+                .subcommand(Command::new("mv")),
+        );
+        assert_eq!("\"post new\"|\"post mv\"", nested_subcommands(&cmd));
+        assert_eq!(vec!["--category"], value_flags(&cmd, "post new"));
+        assert!(flag_cases().contains("        \"post new\":--category) kind=\"categories\" ;;\n"));
+        assert!(flag_cases().contains("        *:-t|*:--team) kind=\"teams\" ;;\n"));
+    }
+
+    #[test]
+    fn test_cache_categories_include_parents() {
+        let cache = CompletionCache {
+            fetched_at: Local::now(),
+            posts: vec![
+                CachedPost {
+                    number: 1,
+                    full_name: String::from("開発/設計/2026/記事"),
+                    category: Some(String::from("開発/設計/2026")),
+                    tags: vec![String::from("tag1")],
+                },
+                CachedPost {
+                    number: 2,
+                    full_name: String::from("日報/記事 #tag1 #タグ2"),
+                    category: Some(String::from("日報")),
+                    tags: vec![String::from("tag1"), String::from("タグ2")],
+                },
+            ],
+        };
+        assert_eq!(
+            vec!["日報", "開発", "開発/設計", "開発/設計/2026"],
+            cache.categories().into_iter().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec!["tag1", "タグ2"],
+            cache.tags().into_iter().collect::<Vec<_>>()
+        );
+    }
+}
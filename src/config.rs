@@ -1,19 +1,23 @@
+use std::collections::HashMap;
 use std::env;
-use std::fs::{self, File};
-use std::io::{BufReader, Write};
+use std::fs;
 use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Mutex, OnceLock};
 
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
-use serde_json::from_reader;
+use serde_json::{Map, Value};
 
-use crate::esa::{Team, TeamId};
+use crate::esa::{AccessToken, Team, TeamId};
+use crate::hooks::Hook;
 
 #[derive(Debug)]
 pub struct Env {
     pub dir_path: PathBuf,
     pub config_file_path: PathBuf,
-    pub tmp_file_path: PathBuf,
-    pub editor_path: PathBuf,
+    pub drafts_dir_path: PathBuf,
+    pub cache_dir_path: PathBuf,
 }
 
 impl Env {
@@ -30,22 +34,23 @@ impl Env {
             config
         };
 
-        let tmp_file_path = {
-            let mut config = dir_path.clone();
-            config.push("edit.md");
-            config
+        let drafts_dir_path = {
+            let mut drafts = dir_path.clone();
+            drafts.push("drafts");
+            drafts
         };
 
-        let editor_path = {
-            let editor = env::var("EDITOR").expect("EDITOR environment variable is not found");
-            PathBuf::from(editor)
+        let cache_dir_path = {
+            let mut cache = dir_path.clone();
+            cache.push("cache");
+            cache
         };
 
         let esa_env = Self {
             dir_path,
             config_file_path,
-            tmp_file_path,
-            editor_path,
+            drafts_dir_path,
+            cache_dir_path,
         };
         esa_env.init();
         esa_env
@@ -65,35 +70,40 @@ impl Env {
             });
         }
 
-        // config file
-        if !self.config_file_path.exists() {
-            let mut file = File::create(&self.tmp_file_path).expect("failed to create config file");
-            // JSON パースエラーにならないように空オブジェクトを入れておく
-            file.write_all("{}".as_bytes())
-                .expect("failed to initialize config file");
-        }
+        // 設定ファイルは作らない。無ければ空の設定として読み、config コマンドで初めて書き込む
 
-        // tmp file
-        if !self.tmp_file_path.exists() {
-            File::create(&self.tmp_file_path).expect("failed to create tmp file");
+        // drafts dir
+        if !self.drafts_dir_path.exists() {
+            fs::create_dir_all(&self.drafts_dir_path).expect("failed to create drafts dir");
         }
     }
 }
 
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Default, Deserialize, PartialEq)]
 pub struct Config {
-    default_team: Option<Team>,
-    teams: Option<Vec<Team>>,
+    default_team: Option<TeamConfig>,
+    teams: Option<Vec<TeamConfig>>,
+    /// Editor command used when neither VISUAL nor EDITOR is set
+    #[serde(default)]
+    editor: Option<String>,
+    /// Commands run on the post before sending
+    #[serde(default)]
+    hooks: Vec<Hook>,
 }
 
 impl Config {
-    pub fn new(env: &Env) -> Self {
-        let config_file = File::open(&env.config_file_path).expect("failed to read config file");
-        let reader = BufReader::new(config_file);
-        from_reader(reader).expect("invalid config file format")
+    /// Reads the config file. Empty if it does not exist yet
+    pub fn new(env: &Env) -> Result<Self> {
+        let path = &env.config_file_path;
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        let config = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&config).with_context(|| format!("invalid config {}", path.display()))
     }
 
-    pub fn get(&self, team_id: TeamId) -> Option<&Team> {
+    pub fn get(&self, team_id: TeamId) -> Option<&TeamConfig> {
         match self.default_team {
             Some(ref team) if team.id == team_id => Some(team),
             _ => match self.teams {
@@ -103,9 +113,312 @@ impl Config {
         }
     }
 
-    pub fn default(&self) -> Option<&Team> {
+    pub fn default(&self) -> Option<&TeamConfig> {
         self.default_team.as_ref()
     }
+
+    pub fn editor(&self) -> Option<&str> {
+        self.editor.as_deref()
+    }
+
+    pub fn hooks(&self) -> &[Hook] {
+        &self.hooks
+    }
+
+    /// All teams. The default team comes first
+    pub fn teams(&self) -> impl Iterator<Item = &TeamConfig> {
+        self.default_team.iter().chain(self.teams.iter().flatten())
+    }
+}
+
+/// Access tokens resolved while the process runs by team ID
+static ACCESS_TOKENS: OnceLock<Mutex<HashMap<String, AccessToken>>> = OnceLock::new();
+
+/// Team in the config.
+/// The access token is written in the config or read from a command, an environment variable or a file
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct TeamConfig {
+    pub id: TeamId,
+    #[serde(default)]
+    access_token: Option<AccessToken>,
+    /// Command printing the token on the first line like `pass show esa/work`
+    #[serde(default)]
+    access_token_command: Option<String>,
+    /// Environment variable holding the token
+    #[serde(default)]
+    access_token_env: Option<String>,
+    /// File containing the token. `~` is the home directory
+    #[serde(default)]
+    access_token_file: Option<PathBuf>,
+}
+
+impl TeamConfig {
+    /// Team with the access token resolved.
+    /// A token read from outside the config is kept while the process runs
+    pub fn resolve(&self) -> Result<Team> {
+        self.check_source()?;
+        let access_token = match self.cached_token() {
+            Some(token) => token,
+            None => {
+                let token = self.read_token()?;
+                let mut tokens = ACCESS_TOKENS.get_or_init(Default::default).lock().unwrap();
+                tokens.insert(self.id.to_string(), token.clone());
+                token
+            }
+        };
+        Ok(Team {
+            id: self.id.clone(),
+            access_token,
+        })
+    }
+
+    /// The token written in the config or resolved already. Never runs the command
+    pub fn cached_token(&self) -> Option<AccessToken> {
+        if let Some(ref token) = self.access_token {
+            return Some(token.clone());
+        }
+        let tokens = ACCESS_TOKENS.get()?.lock().unwrap();
+        tokens.get(&self.id.to_string()).cloned()
+    }
+
+    /// Fails unless exactly one source of the access token is given
+    fn check_source(&self) -> Result<()> {
+        let sources = [
+            self.access_token.is_some(),
+            self.access_token_command.is_some(),
+            self.access_token_env.is_some(),
+            self.access_token_file.is_some(),
+        ];
+        match sources.iter().filter(|given| **given).count() {
+            1 => Ok(()),
+            0 => bail!(
+                "team '{}' needs one of access_token, access_token_command, access_token_env or access_token_file",
+                self.id
+            ),
+            _ => bail!(
+                "team '{}' has more than one of access_token, access_token_command, access_token_env and access_token_file",
+                self.id
+            ),
+        }
+    }
+
+    fn read_token(&self) -> Result<AccessToken> {
+        let token = if let Some(ref command) = self.access_token_command {
+            run_token_command(command)
+                .with_context(|| format!("access_token_command of team '{}' failed", self.id))?
+        } else if let Some(ref name) = self.access_token_env {
+            env::var(name).with_context(|| {
+                format!(
+                    "environment variable {} for team '{}' is not set",
+                    name, self.id
+                )
+            })?
+        } else if let Some(ref path) = self.access_token_file {
+            let path = expand_home(path);
+            fs::read_to_string(&path).with_context(|| {
+                format!(
+                    "failed to read access_token_file {} of team '{}'",
+                    path.display(),
+                    self.id
+                )
+            })?
+        } else {
+            // check_source で access_token だけが指定されていることを確かめている
+            return Ok(self.access_token.clone().expect("access token is checked"));
+        };
+        let token = token.trim();
+        if token.is_empty() {
+            bail!("access token of team '{}' is empty", self.id);
+        }
+        Ok(AccessToken::new(token.to_string()))
+    }
+}
+
+/// Runs the command and returns the first line of its output.
+/// stdin and stderr are left to the terminal to let the command ask a passphrase
+fn run_token_command(command: &str) -> Result<String> {
+    let words = shell_words::split(command).context("invalid command")?;
+    let (program, args) = words.split_first().context("command is empty")?;
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("failed to run `{}`", command))?;
+    if !output.status.success() {
+        bail!("`{}` exited with {}", command, output.status);
+    }
+    let stdout = String::from_utf8(output.stdout).context("output is not UTF-8")?;
+    Ok(stdout.lines().next().unwrap_or("").to_string())
+}
+
+fn expand_home(path: &std::path::Path) -> PathBuf {
+    match (path.strip_prefix("~"), dirs::home_dir()) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Keys edited by `config team` instead of `config set`
+const TEAM_KEYS: [&str; 2] = ["default_team", "teams"];
+
+/// Config file edited as JSON to keep keys unknown to this version
+#[derive(Debug)]
+pub struct ConfigFile {
+    path: PathBuf,
+    value: Map<String, Value>,
+}
+
+impl ConfigFile {
+    pub fn load(env: &Env) -> Result<Self> {
+        let path = env.config_file_path.clone();
+        let value = if path.exists() {
+            let config = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            serde_json::from_str(&config)
+                .with_context(|| format!("invalid config {}", path.display()))?
+        } else {
+            Map::new()
+        };
+        Ok(Self { path, value })
+    }
+
+    /// Writes the file atomically after checking it is still a valid config
+    pub fn save(&self) -> Result<()> {
+        let config = serde_json::from_value::<Config>(Value::Object(self.value.clone()))
+            .context("the change makes the config invalid")?;
+        for team in config.teams() {
+            team.check_source()?;
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        // 書き込み途中で失敗しても元の設定を壊さないように別名で書いてから置き換える
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_string_pretty(&self.value)? + "\n")
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        restrict_permissions(&tmp)?;
+        fs::rename(&tmp, &self.path)
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    fn team_id(team: &Value) -> Option<&str> {
+        team.get("id").and_then(Value::as_str)
+    }
+
+    fn teams_mut(&mut self) -> Result<&mut Vec<Value>> {
+        let teams = self
+            .value
+            .entry("teams")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if teams.is_null() {
+            *teams = Value::Array(Vec::new());
+        }
+        teams.as_array_mut().context("teams is not a list")
+    }
+
+    /// IDs of the default team and the other teams
+    pub fn team_ids(&self) -> (Option<&str>, Vec<&str>) {
+        let default = self.value.get("default_team").and_then(Self::team_id);
+        let teams = self
+            .value
+            .get("teams")
+            .and_then(Value::as_array)
+            .map(|teams| teams.iter().filter_map(Self::team_id).collect())
+            .unwrap_or_default();
+        (default, teams)
+    }
+
+    /// Adds the team. The first team becomes the default team
+    /// # Args
+    /// - fields: Fields of the team except `id` like `access_token`
+    pub fn add_team(&mut self, id: &str, fields: Map<String, Value>, default: bool) -> Result<()> {
+        let (current, teams) = self.team_ids();
+        if current == Some(id) || teams.contains(&id) {
+            bail!("team '{}' already exists", id);
+        }
+        let mut team = Map::new();
+        team.insert(String::from("id"), Value::String(id.to_string()));
+        team.extend(fields);
+        let team = Value::Object(team);
+        if current.is_none() {
+            self.value.insert(String::from("default_team"), team);
+        } else if default {
+            let previous = self.value.insert(String::from("default_team"), team);
+            self.teams_mut()?.extend(previous);
+        } else {
+            self.teams_mut()?.push(team);
+        }
+        Ok(())
+    }
+
+    pub fn remove_team(&mut self, id: &str) -> Result<()> {
+        let (current, _) = self.team_ids();
+        if current == Some(id) {
+            // 残ったチームの先頭を既定にする
+            let teams = self.teams_mut()?;
+            let next = (!teams.is_empty()).then(|| teams.remove(0));
+            match next {
+                Some(team) => self.value.insert(String::from("default_team"), team),
+                None => self.value.remove("default_team"),
+            };
+            return Ok(());
+        }
+        let teams = self.teams_mut()?;
+        let count = teams.len();
+        teams.retain(|team| Self::team_id(team) != Some(id));
+        if teams.len() == count {
+            bail!("team '{}' is not found", id);
+        }
+        Ok(())
+    }
+
+    pub fn set_default_team(&mut self, id: &str) -> Result<()> {
+        let (current, _) = self.team_ids();
+        if current == Some(id) {
+            return Ok(());
+        }
+        let teams = self.teams_mut()?;
+        let index = teams
+            .iter()
+            .position(|team| Self::team_id(team) == Some(id))
+            .with_context(|| format!("team '{}' is not found", id))?;
+        let team = teams.remove(index);
+        let previous = self.value.insert(String::from("default_team"), team);
+        self.teams_mut()?.extend(previous);
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<&Value>> {
+        check_key(key)?;
+        Ok(self.value.get(key))
+    }
+
+    pub fn set(&mut self, key: &str, value: Value) -> Result<()> {
+        check_key(key)?;
+        self.value.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+fn check_key(key: &str) -> Result<()> {
+    if TEAM_KEYS.contains(&key) {
+        bail!("use `esa-cli config team` to change teams");
+    }
+    Ok(())
+}
+
+/// The config has access tokens, so only the user can read it
+#[cfg(unix)]
+fn restrict_permissions(path: &std::path::Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o600))
+        .with_context(|| format!("failed to set permissions of {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn restrict_permissions(_: &std::path::Path) -> Result<()> {
+    Ok(())
 }
 
 #[cfg(test)]
@@ -113,7 +426,15 @@ mod tests {
     use super::*;
     use serde_json::from_str;
 
-    use crate::esa::{AccessToken, Team, TeamId};
+    fn plain(id: &str, access_token: &str) -> TeamConfig {
+        TeamConfig {
+            id: TeamId::new(id.to_string()),
+            access_token: Some(AccessToken::new(access_token.to_string())),
+            access_token_command: None,
+            access_token_env: None,
+            access_token_file: None,
+        }
+    }
 
     #[test]
     fn test_parse_config() {
@@ -140,21 +461,96 @@ mod tests {
             )
             .unwrap(),
             Config {
-                default_team: Some(Team {
-                    id: TeamId::new(String::from("test_team1")),
-                    access_token: AccessToken::new(String::from("test_access_token1")),
-                }),
+                default_team: Some(plain("test_team1", "test_access_token1")),
                 teams: Some(vec![
-                    Team {
-                        id: TeamId::new(String::from("test_team2")),
-                        access_token: AccessToken::new(String::from("test_access_token2")),
-                    },
-                    Team {
-                        id: TeamId::new(String::from("test_team3")),
-                        access_token: AccessToken::new(String::from("test_access_token3")),
-                    },
-                ])
+                    plain("test_team2", "test_access_token2"),
+                    plain("test_team3", "test_access_token3"),
+                ]),
+                editor: None,
+                hooks: vec![],
             }
         );
     }
+
+    #[test]
+    fn test_edit_config_file() {
+        let dir = std::env::temp_dir().join(format!("esa-cli-config-test-{}", std::process::id()));
+        let env = Env::new(Some(dir.clone()));
+        let empty: Config = Default::default();
+        assert_eq!(empty, Config::new(&env).unwrap());
+
+        let mut config = ConfigFile::load(&env).unwrap();
+        config.set("editor", Value::from("vim")).unwrap();
+        config.set("unknown", Value::from(1)).unwrap();
+        let token = |token: &str| {
+            [(String::from("access_token"), Value::from(token))]
+                .into_iter()
+                .collect::<Map<_, _>>()
+        };
+        config.add_team("t1", token("a"), false).unwrap();
+        config.add_team("t2", token("b"), false).unwrap();
+        config.add_team("t3", token("c"), true).unwrap();
+        assert!(config.add_team("t2", token("d"), false).is_err());
+        assert!(config.set("teams", Value::Null).is_err());
+        assert_eq!((Some("t3"), vec!["t2", "t1"]), config.team_ids());
+        config.set_default_team("t1").unwrap();
+        config.remove_team("t2").unwrap();
+        assert!(config.remove_team("t9").is_err());
+        config.save().unwrap();
+
+        let config = ConfigFile::load(&env).unwrap();
+        assert_eq!((Some("t1"), vec!["t3"]), config.team_ids());
+        assert_eq!(Some(&Value::from(1)), config.get("unknown").unwrap());
+        let parsed = Config::new(&env).unwrap();
+        assert_eq!(Some("vim"), parsed.editor());
+        assert_eq!(
+            Some(&TeamId::new(String::from("t1"))),
+            parsed.default().map(|team| &team.id)
+        );
+
+        let mut config = config;
+        config.remove_team("t1").unwrap();
+        assert_eq!((Some("t3"), vec![]), config.team_ids());
+        config.remove_team("t3").unwrap();
+        assert_eq!((None, vec![]), config.team_ids());
+
+        config.set("hooks", Value::from("not a list")).unwrap();
+        assert!(config.save().is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_access_token() {
+        let team = |json: &str| serde_json::from_str::<TeamConfig>(json).unwrap();
+        let token = |team: &TeamConfig| team.resolve().map(|team| team.access_token.to_string());
+
+        assert_eq!(
+            "plain",
+            token(&team(r#"{"id": "t1", "access_token": "plain"}"#)).unwrap()
+        );
+        let command = team(r#"{"id": "t2", "access_token_command": "printf 'secret\\nmeta'"}"#);
+        assert_eq!(None, command.cached_token());
+        assert_eq!("secret", token(&command).unwrap());
+        assert_eq!(
+            Some(String::from("secret")),
+            command.cached_token().map(|token| token.to_string())
+        );
+
+        let failed = team(r#"{"id": "t3", "access_token_command": "false"}"#);
+        let message = format!("{:#}", failed.resolve().unwrap_err());
+        assert!(
+            message.contains("access_token_command of team 't3' failed"),
+            "{}",
+            message
+        );
+        assert!(token(&team(
+            r#"{"id": "t4", "access_token_env": "ESA_CLI_TEST_UNSET_TOKEN"}"#
+        ))
+        .is_err());
+        assert!(token(&team(r#"{"id": "t5"}"#)).is_err());
+        assert!(token(&team(
+            r#"{"id": "t6", "access_token": "a", "access_token_file": "/dev/null"}"#
+        ))
+        .is_err());
+    }
 }
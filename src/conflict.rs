@@ -0,0 +1,71 @@
+/// Result of merging concurrent edits
+#[derive(Debug, PartialEq)]
+pub enum Merged {
+    /// Both edits are merged cleanly
+    Clean(String),
+    /// Text containing conflict markers
+    Conflicted(String),
+}
+
+impl Merged {
+    pub fn text(&self) -> &str {
+        match self {
+            Merged::Clean(text) | Merged::Conflicted(text) => text,
+        }
+    }
+}
+
+/// Merges `ours` and `theirs` which are both edited from `base`
+pub fn merge(base: &str, ours: &str, theirs: &str) -> Merged {
+    match diffy::merge(base, ours, theirs) {
+        Ok(text) => Merged::Clean(text),
+        Err(text) => Merged::Conflicted(text),
+    }
+}
+
+/// 1-based line number of the first conflict left in `text`,
+/// a `<<<<<<<` line closed by a `>>>>>>>` line.
+/// `=======` alone is not a marker since it underlines a heading in Markdown
+pub fn marker_line(text: &str) -> Option<usize> {
+    let lines = text.lines().collect::<Vec<_>>();
+    let start = lines.iter().position(|line| line.starts_with("<<<<<<<"))?;
+    lines[start + 1..]
+        .iter()
+        .any(|line| line.starts_with(">>>>>>>"))
+        .then_some(start + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge_clean() {
+        assert_eq!(
+            Merged::Clean(String::from("title 2\n\nbody\n\nfooter 2\n")),
+            merge(
+                "title\n\nbody\n\nfooter\n",
+                "title 2\n\nbody\n\nfooter\n",
+                "title\n\nbody\n\nfooter 2\n"
+            )
+        );
+    }
+
+    #[test]
+    fn test_merge_conflicted() {
+        let merged = merge("手順 1\n", "手順 1 (ours)\n", "手順 1 (theirs)\n");
+        assert!(matches!(merged, Merged::Conflicted(_)));
+        assert_eq!(Some(1), marker_line(merged.text()));
+    }
+
+    #[test]
+    fn test_marker_line() {
+        assert_eq!(None, marker_line("# title\n\n=== not a marker\n"));
+        assert_eq!(None, marker_line("見出し\n=======\n\nbody\n"));
+        assert_eq!(None, marker_line("<<<<<<< quoted\n"));
+        assert_eq!(
+            Some(2),
+            marker_line("a\n<<<<<<< ours\nb\n=======\nc\n>>>>>>> theirs\n")
+        );
+    }
+}
@@ -0,0 +1,120 @@
+use std::fmt::Write as _;
+use std::io::{self, IsTerminal};
+
+use diffy::{create_patch, PatchFormatter};
+
+use esa::post::Post;
+
+use super::tmp_file::ParsedPost;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Colors the output only when stdout is a terminal
+pub fn use_color() -> bool {
+    io::stdout().is_terminal()
+}
+
+/// Unified diff of `original` and `modified`. Empty if they are the same
+pub fn unified_diff(label: &str, original: &str, modified: &str, color: bool) -> String {
+    if original == modified {
+        return String::new();
+    }
+    let patch = create_patch(original, modified);
+    let formatter = if color {
+        PatchFormatter::new().with_color()
+    } else {
+        PatchFormatter::new()
+    };
+    let rendered = formatter.fmt_patch(&patch).to_string();
+
+    // diffy のヘッダ (--- original / +++ modified) をラベル付きのものに置き換える
+    let mut diff = header(&format!("--- a/{}\n+++ b/{}", label, label), color);
+    for line in rendered.lines().skip(2) {
+        diff.push_str(line);
+        diff.push('\n');
+    }
+    diff
+}
+
+/// Diff between the post on esa and the edited content
+pub fn post_diff(post: &Post, edited: &ParsedPost, color: bool) -> String {
+    let content = &edited.content;
+    let mut diff = String::new();
+    field_diff(&mut diff, "title", &post.name, &content.name, color);
+    field_diff(
+        &mut diff,
+        "category",
+        post.category.as_deref().unwrap_or(""),
+        content.category.as_deref().unwrap_or(""),
+        color,
+    );
+    field_diff(
+        &mut diff,
+        "tags",
+        &post.tags.join(", "),
+        &content.tags.join(", "),
+        color,
+    );
+    if let Some(wip) = edited.wip {
+        field_diff(
+            &mut diff,
+            "wip",
+            &post.wip.to_string(),
+            &wip.to_string(),
+            color,
+        );
+    }
+    diff.push_str(&unified_diff(
+        "body",
+        &with_newline(&post.body_md),
+        &with_newline(content.body_md.as_deref().unwrap_or("")),
+        color,
+    ));
+    diff
+}
+
+fn field_diff(diff: &mut String, name: &str, original: &str, modified: &str, color: bool) {
+    if original == modified {
+        return;
+    }
+    diff.push_str(&header(name, color));
+    let _ = writeln!(diff, "{}", paint(&format!("- {}", original), RED, color));
+    let _ = writeln!(diff, "{}", paint(&format!("+ {}", modified), GREEN, color));
+}
+
+fn header(text: &str, color: bool) -> String {
+    format!("{}\n", paint(text, BOLD, color))
+}
+
+fn paint(text: &str, style: &str, color: bool) -> String {
+    if color {
+        format!("{}{}{}", style, text, RESET)
+    } else {
+        text.to_string()
+    }
+}
+
+fn with_newline(text: &str) -> String {
+    if text.is_empty() || text.ends_with('\n') {
+        text.to_string()
+    } else {
+        format!("{}\n", text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unified_diff() {
+        assert_eq!("", unified_diff("body", "a\n", "a\n", false));
+        assert_eq!(
+            "--- a/body\n+++ b/body\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+            unified_diff("body", "a\nb\n", "a\nc\n", false)
+        );
+    }
+}
@@ -0,0 +1,142 @@
+use std::fmt::Write as _;
+use std::fs::{self, File};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::{Arg, Command};
+use clap_mangen::Man;
+
+/// Writes man pages into `<dir>/man` and the Markdown reference into `<dir>/esa-cli.md`
+pub fn generate(mut cmd: Command, dir: &Path) -> Result<()> {
+    cmd.build();
+
+    let man_dir = dir.join("man");
+    fs::create_dir_all(&man_dir)
+        .with_context(|| format!("failed to create directory {}", man_dir.display()))?;
+    write_man_pages(&cmd, cmd.get_name(), &man_dir)?;
+
+    let reference_path = dir.join(format!("{}.md", cmd.get_name()));
+    fs::write(&reference_path, markdown(&cmd))
+        .with_context(|| format!("failed to write {}", reference_path.display()))?;
+    Ok(())
+}
+
+/// Writes `<name>.1` and `<name>-<subcommand>.1` recursively
+fn write_man_pages(cmd: &Command, name: &str, dir: &Path) -> Result<()> {
+    let page = cmd.clone().name(name.to_string());
+    let path = dir.join(format!("{}.1", name));
+    let mut file =
+        File::create(&path).with_context(|| format!("failed to create {}", path.display()))?;
+    Man::new(page).render(&mut file)?;
+
+    for sub in visible_subcommands(cmd) {
+        write_man_pages(sub, &format!("{}-{}", name, sub.get_name()), dir)?;
+    }
+    Ok(())
+}
+
+fn visible_subcommands<'a, 'help>(
+    cmd: &'a Command<'help>,
+) -> impl Iterator<Item = &'a Command<'help>> {
+    cmd.get_subcommands()
+        .filter(|sub| !sub.is_hide_set() && sub.get_name() != "help")
+}
+
+fn markdown(cmd: &Command) -> String {
+    let mut doc = format!("# {} command reference\n\n", cmd.get_name());
+    write_command_markdown(&mut doc, cmd, cmd.get_name());
+    doc
+}
+
+fn write_command_markdown(doc: &mut String, cmd: &Command, name: &str) {
+    let mut usage_cmd = cmd.clone().name(name.to_string());
+    let usage = usage_cmd.render_usage();
+
+    let _ = writeln!(doc, "## {}\n", name);
+    if let Some(about) = cmd.get_long_about().or_else(|| cmd.get_about()) {
+        let _ = writeln!(doc, "{}\n", about);
+    }
+    let _ = writeln!(
+        doc,
+        "```\n{}\n```\n",
+        usage.trim_start_matches("USAGE:").trim()
+    );
+
+    let positionals = cmd
+        .get_positionals()
+        .filter(|arg| !arg.is_hide_set())
+        .collect::<Vec<_>>();
+    if !positionals.is_empty() {
+        let _ = writeln!(doc, "### Arguments\n");
+        for arg in positionals {
+            let _ = writeln!(doc, "- `<{}>`: {}", value_name(arg), help_text(arg));
+        }
+        doc.push('\n');
+    }
+
+    let options = cmd
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+        .collect::<Vec<_>>();
+    if !options.is_empty() {
+        let _ = writeln!(doc, "### Options\n");
+        for arg in options {
+            let _ = writeln!(doc, "- `{}`: {}", option_signature(arg), help_text(arg));
+        }
+        doc.push('\n');
+    }
+
+    if let Some(after_help) = cmd.get_after_long_help().or_else(|| cmd.get_after_help()) {
+        let _ = writeln!(doc, "```\n{}\n```\n", after_help);
+    }
+
+    for sub in visible_subcommands(cmd) {
+        write_command_markdown(doc, sub, &format!("{} {}", name, sub.get_name()));
+    }
+}
+
+fn option_signature(arg: &Arg) -> String {
+    let mut names = Vec::new();
+    if let Some(short) = arg.get_short() {
+        names.push(format!("-{}", short));
+    }
+    if let Some(long) = arg.get_long() {
+        names.push(format!("--{}", long));
+    }
+    let mut signature = names.join(", ");
+    if arg.is_takes_value_set() {
+        let _ = write!(signature, " <{}>", value_name(arg));
+    }
+    signature
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first().copied())
+        .unwrap_or_else(|| arg.get_id())
+        .to_uppercase()
+}
+
+/// Help text in one line with possible values
+fn help_text(arg: &Arg) -> String {
+    let help = arg
+        .get_long_help()
+        .or_else(|| arg.get_help())
+        .unwrap_or_default()
+        .lines()
+        .map(str::trim)
+        .collect::<Vec<_>>()
+        .join(" ");
+    let possible_values = arg
+        .get_possible_values()
+        .unwrap_or_default()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| format!("`{}`", value.get_name()))
+        .collect::<Vec<_>>();
+    if possible_values.is_empty() {
+        help
+    } else {
+        format!("{} (possible values: {})", help, possible_values.join(", "))
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+
+use esa::{
+    post::{Order, Post, SearchQuery, Sort},
+    Esa,
+};
+
+use super::assets::{root_of, Assets};
+use super::cache;
+use super::config::Env;
+use super::paging::Pages;
+use super::workspace;
+
+/// Search index loaded by the top page. A script instead of JSON to work with `file://`
+const SEARCH_INDEX_FILE: &str = "search-index.js";
+
+const STYLE: &str =
+    "body{max-width:960px;margin:0 auto;padding:0 1em;font-family:sans-serif;line-height:1.6}\
+nav{padding:.5em 0;border-bottom:1px solid #ccc}nav a{margin-right:1em}\
+pre{overflow:auto;background:#f6f6f6;padding:.5em}img{max-width:100%}\
+.meta{color:#666;font-size:.9em}.wip{color:#c33}";
+
+const SEARCH_SCRIPT: &str = r#"const input = document.getElementById("search");
+const results = document.getElementById("results");
+input.addEventListener("input", () => {
+  const terms = input.value.toLowerCase().split(/\s+/).filter((t) => t);
+  results.textContent = "";
+  if (terms.length === 0) return;
+  for (const post of SEARCH_INDEX) {
+    const text = (post.full_name + " " + post.tags.join(" ") + " " + post.body).toLowerCase();
+    if (!terms.every((t) => text.includes(t))) continue;
+    const li = document.createElement("li");
+    const a = document.createElement("a");
+    a.href = "posts/" + post.number + ".html";
+    a.textContent = post.full_name;
+    li.appendChild(a);
+    results.appendChild(li);
+  }
+});"#;
+
+/// Export posts as a static HTML site readable without esa or the network
+/// # Args
+/// - dir: Directory to write into. Pages of a previous export are replaced
+/// - query: Search query of the posts. `None` exports all posts
+/// - attachments: Downloads attached files into `assets/` to view them offline
+pub async fn export_html(
+    esa: &Esa,
+    esa_env: &Env,
+    dir: &Path,
+    query: Option<String>,
+    attachments: bool,
+) -> Result<()> {
+    prepare_dir(dir)?;
+
+    let mut posts = Vec::new();
+    let search_query = SearchQuery::new(query, None, Some(Sort::Number(Order::Asc)));
+    let mut pages = Pages::new(search_query).with_progress();
+    while let Some(page) = pages.next(esa).await? {
+        cache::remember(esa_env, &esa.team_id().to_string(), &page);
+        posts.extend(page);
+    }
+
+    // 前回のエクスポートで取得したファイルは取得し直さない
+    let mut assets = Assets::open(dir)?;
+    if attachments {
+        for post in &posts {
+            assets.fetch(esa, &post.body_html).await?;
+        }
+    }
+
+    let site = Site::new(esa.team_id().to_string(), &posts, &assets);
+    for post in &posts {
+        write(
+            dir,
+            &format!("posts/{}.html", post.number),
+            &site.post_page(post),
+        )?;
+    }
+    for category in site.categories() {
+        write(
+            dir,
+            &category_path(&category),
+            &site.category_page(&category),
+        )?;
+    }
+    write(dir, "tags.html", &site.tags_page())?;
+    write(dir, SEARCH_INDEX_FILE, &site.search_index()?)?;
+
+    println!(
+        "exported {} posts into {}",
+        posts.len(),
+        dir.join("index.html").display()
+    );
+    Ok(())
+}
+
+/// Removes pages of a previous export. Refuses a directory with other files
+fn prepare_dir(dir: &Path) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    let exported = dir.join(SEARCH_INDEX_FILE).exists();
+    let empty = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .next()
+        .is_none();
+    if !exported && !empty {
+        bail!("{} is not empty and not an exported site", dir.display());
+    }
+    // 削除された記事やカテゴリのページが残らないように作り直す
+    for sub in ["posts", "categories"] {
+        let path = dir.join(sub);
+        if path.exists() {
+            fs::remove_dir_all(&path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+fn write(dir: &Path, path: &str, content: &str) -> Result<()> {
+    let path = dir.join(path);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&path, content).with_context(|| format!("failed to write {}", path.display()))
+}
+
+/// Path of the index page of the category. The top page for the root category `""`
+fn category_path(category: &str) -> String {
+    if category.is_empty() {
+        return String::from("index.html");
+    }
+    let dir = category
+        .split('/')
+        .map(workspace::sanitize)
+        .collect::<Vec<_>>()
+        .join("/");
+    format!("categories/{}/index.html", dir)
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn layout(title: &str, root: &str, content: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n<nav><a href=\"{root}index.html\">Top</a><a href=\"{root}tags.html\">Tags</a></nav>\n{content}\n</body>\n</html>\n",
+        title = escape(title),
+        style = STYLE,
+        root = root,
+        content = content
+    )
+}
+
+/// Rewrites links to the exported posts into links to the local pages
+/// and other links into esa to absolute URLs.
+/// Returned links are relative to the `posts` directory
+pub fn rewrite_links(html: &str, team: &str, numbers: &HashSet<i32>) -> String {
+    let pattern = Regex::new(&format!(
+        r#"(href|src)="(?:https://{}\.esa\.io)?(/[^"]*)""#,
+        regex::escape(team)
+    ))
+    .expect("invalid link pattern");
+    let post = Regex::new(r"^/posts/(\d+)(#.*)?$").expect("invalid link pattern");
+    pattern
+        .replace_all(html, |caps: &Captures| {
+            let number = post.captures(&caps[2]).and_then(|post| {
+                let number = post[1].parse::<i32>().ok()?;
+                let anchor = post.get(2).map_or("", |anchor| anchor.as_str());
+                numbers.contains(&number).then_some((number, anchor))
+            });
+            match number {
+                Some((number, anchor)) => format!("{}=\"{}.html{}\"", &caps[1], number, anchor),
+                // `//` で始まるものはプロトコル相対 URL なのでそのまま
+                None if caps[2].starts_with("//") => caps[0].to_string(),
+                None => format!("{}=\"https://{}.esa.io{}\"", &caps[1], team, &caps[2]),
+            }
+        })
+        .to_string()
+}
+
+struct Site<'a> {
+    team: String,
+    posts: &'a [Post],
+    numbers: HashSet<i32>,
+    assets: &'a Assets,
+}
+
+impl<'a> Site<'a> {
+    fn new(team: String, posts: &'a [Post], assets: &'a Assets) -> Self {
+        Self {
+            team,
+            posts,
+            numbers: posts.iter().map(|post| post.number).collect(),
+            assets,
+        }
+    }
+
+    /// All categories including parents without posts and the root category `""`
+    fn categories(&self) -> BTreeSet<String> {
+        let mut categories = BTreeSet::from([String::new()]);
+        for category in self
+            .posts
+            .iter()
+            .filter_map(|post| post.category.as_deref())
+        {
+            let mut path = String::new();
+            for segment in category.split('/').filter(|s| !s.is_empty()) {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(segment);
+                categories.insert(path.clone());
+            }
+        }
+        categories
+    }
+
+    fn post_link(&self, post: &Post, root: &str) -> String {
+        let wip = if post.wip {
+            "<span class=\"wip\">[WIP]</span> "
+        } else {
+            ""
+        };
+        format!(
+            "<li>{}<a href=\"{}posts/{}.html\">{}</a></li>",
+            wip,
+            root,
+            post.number,
+            escape(&post.name)
+        )
+    }
+
+    fn post_page(&self, post: &Post) -> String {
+        let root = "../";
+        let mut content = String::new();
+        if let Some(category) = post.category.as_deref().filter(|c| !c.is_empty()) {
+            let mut path = String::new();
+            let mut breadcrumbs = Vec::new();
+            for segment in category.split('/').filter(|s| !s.is_empty()) {
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(segment);
+                breadcrumbs.push(format!(
+                    "<a href=\"{}{}\">{}</a>",
+                    root,
+                    category_path(&path),
+                    escape(segment)
+                ));
+            }
+            content.push_str(&format!("<p>{}</p>\n", breadcrumbs.join(" / ")));
+        }
+        let wip = if post.wip {
+            "<span class=\"wip\">[WIP]</span> "
+        } else {
+            ""
+        };
+        content.push_str(&format!("<h1>{}{}</h1>\n", wip, escape(&post.name)));
+        let tags = post
+            .tags
+            .iter()
+            .map(|tag| {
+                format!(
+                    "<a href=\"{}tags.html#{}\">#{}</a>",
+                    root,
+                    escape(tag),
+                    escape(tag)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+        content.push_str(&format!(
+            "<p class=\"meta\">#{} updated at {} by {} {} <a href=\"{}\">esa</a></p>\n",
+            post.number,
+            post.updated_at.format("%Y-%m-%d %H:%M"),
+            escape(&post.updated_by.screen_name),
+            tags,
+            escape(&post.url)
+        ));
+        let body = self.assets.relink(&post.body_html, root);
+        content.push_str(&rewrite_links(&body, &self.team, &self.numbers));
+        layout(&post.full_name, root, &content)
+    }
+
+    fn category_page(&self, category: &str) -> String {
+        let path = category_path(category);
+        let root = root_of(&path);
+        let mut content = String::new();
+        if category.is_empty() {
+            content.push_str(&format!("<h1>{}</h1>\n", escape(&self.team)));
+            content.push_str(
+                "<input id=\"search\" type=\"search\" placeholder=\"Search\" autofocus>\n<ul id=\"results\"></ul>\n",
+            );
+        } else {
+            content.push_str(&format!("<h1>{}</h1>\n", escape(category)));
+        }
+
+        let prefix = if category.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", category)
+        };
+        let children = self
+            .categories()
+            .into_iter()
+            .filter(|c| {
+                c.strip_prefix(&prefix)
+                    .is_some_and(|rest| !rest.is_empty() && !rest.contains('/'))
+            })
+            .collect::<Vec<_>>();
+        if !children.is_empty() {
+            content.push_str("<h2>Categories</h2>\n<ul>\n");
+            for child in children {
+                content.push_str(&format!(
+                    "<li><a href=\"{}{}\">{}</a></li>\n",
+                    root,
+                    category_path(&child),
+                    escape(&child[prefix.len()..])
+                ));
+            }
+            content.push_str("</ul>\n");
+        }
+
+        let posts = self
+            .posts
+            .iter()
+            .filter(|post| post.category.as_deref().unwrap_or("").trim_matches('/') == category)
+            .collect::<Vec<_>>();
+        if !posts.is_empty() {
+            content.push_str("<h2>Posts</h2>\n<ul>\n");
+            for post in posts {
+                content.push_str(&self.post_link(post, &root));
+                content.push('\n');
+            }
+            content.push_str("</ul>\n");
+        }
+
+        if category.is_empty() {
+            content.push_str(&format!(
+                "<script src=\"{}\"></script>\n<script>\n{}\n</script>\n",
+                SEARCH_INDEX_FILE, SEARCH_SCRIPT
+            ));
+            layout(&self.team, &root, &content)
+        } else {
+            layout(category, &root, &content)
+        }
+    }
+
+    fn tags_page(&self) -> String {
+        let mut tags: BTreeMap<&str, Vec<&Post>> = BTreeMap::new();
+        for post in self.posts {
+            for tag in &post.tags {
+                tags.entry(tag).or_default().push(post);
+            }
+        }
+        let mut content = String::from("<h1>Tags</h1>\n<ul>\n");
+        for (tag, posts) in &tags {
+            content.push_str(&format!(
+                "<li><a href=\"#{}\">#{}</a> ({})</li>\n",
+                escape(tag),
+                escape(tag),
+                posts.len()
+            ));
+        }
+        content.push_str("</ul>\n");
+        for (tag, posts) in &tags {
+            content.push_str(&format!(
+                "<h2 id=\"{}\">#{}</h2>\n<ul>\n",
+                escape(tag),
+                escape(tag)
+            ));
+            for post in posts {
+                content.push_str(&self.post_link(post, ""));
+                content.push('\n');
+            }
+            content.push_str("</ul>\n");
+        }
+        layout("Tags", "", &content)
+    }
+
+    fn search_index(&self) -> Result<String> {
+        let index = self
+            .posts
+            .iter()
+            .map(|post| {
+                serde_json::json!({
+                    "number": post.number,
+                    "full_name": post.full_name,
+                    "tags": post.tags,
+                    "body": post.body_md,
+                })
+            })
+            .collect::<Vec<_>>();
+        Ok(format!(
+            "const SEARCH_INDEX = {};\n",
+            serde_json::to_string(&index)?
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewrite_links() {
+        let numbers = [1, 2].into_iter().collect::<HashSet<_>>();
+        assert_eq!(
+            r#"<a href="1.html">a</a> <a href="2.html#手順">b</a> <a href="https://docs.esa.io/posts/3">c</a> <a href="https://docs.esa.io/posts/1/edit">d</a> <img src="https://docs.esa.io/uploads/x.png"> <a href="https://other.esa.io/posts/1">e</a>"#,
+            rewrite_links(
+                r#"<a href="/posts/1">a</a> <a href="https://docs.esa.io/posts/2#手順">b</a> <a href="/posts/3">c</a> <a href="/posts/1/edit">d</a> <img src="/uploads/x.png"> <a href="https://other.esa.io/posts/1">e</a>"#,
+                "docs",
+                &numbers
+            )
+        );
+    }
+
+    #[test]
+    fn test_category_path() {
+        assert_eq!("index.html", category_path(""));
+        assert_eq!(
+            "categories/手順書/a_b/index.html",
+            category_path("手順書/a:b")
+        );
+        assert_eq!("../../../", root_of(&category_path("手順書/a:b")));
+        assert_eq!("", root_of("tags.html"));
+    }
+}
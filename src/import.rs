@@ -0,0 +1,486 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use serde_yaml::Value;
+
+use esa::{post::PostContent, Esa};
+
+use super::rate_limit;
+use super::restore;
+use super::workspace;
+
+/// Progress of importing kept in the imported directory to resume after interruption
+const STATE_FILE: &str = ".esa-import.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ImportState {
+    team: String,
+    /// Imported files by path relative to the directory
+    posts: BTreeMap<String, Imported>,
+    /// URLs of uploaded images by path relative to the directory
+    uploads: BTreeMap<String, String>,
+    /// Path of the file being created as a post.
+    /// The post may have been created if the import was interrupted
+    #[serde(default)]
+    pending: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Imported {
+    number: i32,
+    links_updated: bool,
+}
+
+/// Markdown file to import
+#[derive(Debug, PartialEq)]
+pub struct SourceFile {
+    /// Path relative to the directory separated by `/`
+    pub path: String,
+    pub title: String,
+    pub tags: Vec<String>,
+    pub wip: bool,
+    pub date: Option<String>,
+    pub body: String,
+}
+
+impl SourceFile {
+    /// Reads `title`, `tags`, `date` and `draft` (or `published`) in YAML front matter.
+    /// The file name is the title if not given
+    pub fn parse(path: &str, content: &str) -> Result<Self> {
+        let (front_matter, body) =
+            split_yaml(content).with_context(|| format!("invalid front matter in {}", path))?;
+        let get = |key: &str| front_matter.as_ref().and_then(|v| v.get(key));
+
+        let stem = path.rsplit('/').next().unwrap_or(path);
+        let stem = stem.strip_suffix(".md").unwrap_or(stem);
+        let title = get("title")
+            .and_then(scalar)
+            .filter(|title| !title.trim().is_empty())
+            .unwrap_or_else(|| stem.to_string());
+        let tags = match get("tags") {
+            Some(Value::Sequence(tags)) => tags.iter().filter_map(scalar).collect(),
+            Some(tags) => scalar(tags)
+                .map(|tags| {
+                    tags.split(|c: char| c == ',' || c.is_whitespace())
+                        .map(str::to_string)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            None => Vec::new(),
+        };
+        let tags = tags
+            .iter()
+            .map(|tag: &String| tag.trim().trim_start_matches('#').to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        let wip = match (get("draft"), get("published")) {
+            (Some(Value::Bool(draft)), _) => *draft,
+            (_, Some(Value::Bool(published))) => !*published,
+            _ => false,
+        };
+
+        Ok(Self {
+            path: path.to_string(),
+            title,
+            tags,
+            wip,
+            date: get("date").and_then(scalar),
+            body: body.to_string(),
+        })
+    }
+
+    /// Category from `root` and the directory of the file
+    pub fn category(&self, root: Option<&str>) -> Option<String> {
+        let dir = self.path.rsplit_once('/').map(|(dir, _)| dir);
+        let category = [root.map(|r| r.trim_matches('/')), dir]
+            .iter()
+            .flatten()
+            .filter(|c| !c.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join("/");
+        if category.is_empty() {
+            None
+        } else {
+            Some(category)
+        }
+    }
+}
+
+fn scalar(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+/// Splits YAML front matter delimited by `---` from the body
+fn split_yaml(content: &str) -> Result<(Option<Value>, &str)> {
+    let rest = match content
+        .strip_prefix("---\n")
+        .or_else(|| content.strip_prefix("---\r\n"))
+    {
+        Some(rest) => rest,
+        None => return Ok((None, content)),
+    };
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == "---" {
+            let yaml = &rest[..offset];
+            let front_matter = if yaml.trim().is_empty() {
+                None
+            } else {
+                Some(serde_yaml::from_str(yaml)?)
+            };
+            return Ok((front_matter, &rest[offset + line.len()..]));
+        }
+        offset += line.len();
+    }
+    bail!("front matter is not closed with '---'")
+}
+
+/// Resolves `target` written in the file at `from` into a path relative to the directory
+fn resolve(from: &str, target: &str) -> Option<String> {
+    if target.contains("://") || target.starts_with(['/', '#']) || target.starts_with("mailto:") {
+        return None;
+    }
+    let target = target.replace("%20", " ");
+    let mut segments = from.split('/').collect::<Vec<_>>();
+    segments.pop();
+    for segment in target.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    Some(segments.join("/"))
+}
+
+fn markdown_link() -> Regex {
+    Regex::new(r#"(!?)\[([^\]]*)\]\(([^)\s]+)((?:\s+"[^"]*")?)\)"#).expect("invalid link pattern")
+}
+
+fn wiki_link() -> Regex {
+    Regex::new(r"(!?)\[\[([^\]|#]+)(#[^\]|]*)?(?:\|([^\]]*))?\]\]").expect("invalid link pattern")
+}
+
+/// Local images referenced from the file as paths relative to the directory,
+/// and the references not found in the directory such as `data:` URIs
+/// # Args
+/// - files: All files in the directory to resolve `![[name]]`
+pub fn local_images(file: &SourceFile, files: &[String]) -> (Vec<String>, Vec<String>) {
+    let mut images = Vec::new();
+    let mut missing = Vec::new();
+    for caps in markdown_link().captures_iter(&file.body) {
+        if &caps[1] != "!" || caps[3].contains("://") {
+            continue;
+        }
+        match resolve(&file.path, &caps[3]).filter(|path| files.contains(path)) {
+            Some(path) => images.push(path),
+            None => missing.push(caps[3].to_string()),
+        }
+    }
+    for caps in wiki_link().captures_iter(&file.body) {
+        if &caps[1] != "!" {
+            continue;
+        }
+        match find_by_name(files, caps[2].trim()) {
+            Some(path) => images.push(path),
+            None => missing.push(caps[2].trim().to_string()),
+        }
+    }
+    images.sort();
+    images.dedup();
+    missing.sort();
+    missing.dedup();
+    (images, missing)
+}
+
+/// File whose name or path is `name` as Obsidian resolves `[[name]]`
+fn find_by_name(files: &[String], name: &str) -> Option<String> {
+    let with_md = format!("{}.md", name);
+    files
+        .iter()
+        .find(|f| *f == name || **f == with_md)
+        .or_else(|| {
+            files.iter().find(|f| {
+                let file_name = f.rsplit('/').next().unwrap_or(f);
+                file_name == name || file_name == with_md
+            })
+        })
+        .cloned()
+}
+
+/// Rewrites links to local files into esa URLs
+/// # Args
+/// - urls: URLs by path relative to the directory
+/// - files: All files in the directory to resolve `[[name]]`
+pub fn rewrite_links(
+    file: &SourceFile,
+    body: &str,
+    urls: &HashMap<String, String>,
+    files: &[String],
+) -> String {
+    let body = markdown_link().replace_all(body, |caps: &Captures| {
+        let (target, anchor) = match caps[3].split_once('#') {
+            Some((target, anchor)) => (target, format!("#{}", anchor)),
+            None => (&caps[3], String::new()),
+        };
+        match resolve(&file.path, target).and_then(|path| urls.get(&path)) {
+            Some(url) => format!("{}[{}]({}{}{})", &caps[1], &caps[2], url, anchor, &caps[4]),
+            None => caps[0].to_string(),
+        }
+    });
+    wiki_link()
+        .replace_all(&body, |caps: &Captures| {
+            let name = caps[2].trim();
+            match find_by_name(files, name).and_then(|path| urls.get(&path)) {
+                Some(url) => {
+                    let text = caps.get(4).map_or(name, |alias| alias.as_str());
+                    let anchor = caps.get(3).map_or("", |anchor| anchor.as_str());
+                    format!("{}[{}]({}{})", &caps[1], text, url, anchor)
+                }
+                None => caps[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+fn content_type(path: &str) -> &'static str {
+    let extension = path.rsplit('.').next().unwrap_or("").to_lowercase();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Import Markdown files in the directory as posts.
+/// Running again resumes an interrupted import
+/// # Args
+/// - dir: Directory of Markdown files like an Obsidian vault or a Hugo content directory
+/// - category_root: Category under which the directory tree is created
+pub async fn import(esa: &Esa, dir: &Path, category_root: Option<&str>) -> Result<()> {
+    let state_path = dir.join(STATE_FILE);
+    let mut state: ImportState = if state_path.exists() {
+        let state = fs::read_to_string(&state_path)
+            .with_context(|| format!("failed to read {}", state_path.display()))?;
+        serde_json::from_str(&state).with_context(|| format!("invalid {}", state_path.display()))?
+    } else {
+        ImportState {
+            team: esa.team_id().to_string(),
+            ..ImportState::default()
+        }
+    };
+    if state.team != esa.team_id().to_string() {
+        bail!(
+            "{} is being imported into team '{}'",
+            dir.display(),
+            state.team
+        );
+    }
+    let save = |state: &ImportState| -> Result<()> {
+        fs::write(&state_path, serde_json::to_string_pretty(state)?)
+            .with_context(|| format!("failed to write {}", state_path.display()))
+    };
+
+    let mut files = Vec::new();
+    workspace::collect_files(dir, "", &mut files)?;
+    files.sort();
+    let sources = files
+        .iter()
+        .filter(|path| path.ends_with(".md"))
+        .map(|path| {
+            let content = fs::read_to_string(dir.join(path))
+                .with_context(|| format!("failed to read {}", path))?;
+            SourceFile::parse(path, &content)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // 画像をアップロードしてから記事を作り、全記事の番号が決まった後で記事間のリンクを書き換える
+    for source in &sources {
+        if state.posts.contains_key(&source.path) {
+            continue;
+        }
+        let (images, missing) = local_images(source, &files);
+        // 見つからない画像はリンクをそのまま残す
+        for target in missing {
+            eprintln!(
+                "warning: {} refers to {} which is not in the directory. the link is left as is",
+                source.path, target
+            );
+        }
+        for image in images {
+            if state.uploads.contains_key(&image) {
+                continue;
+            }
+            let path = dir.join(&image);
+            let content =
+                fs::read(&path).with_context(|| format!("failed to read {}", path.display()))?;
+            let name = image.rsplit('/').next().unwrap_or(&image);
+            let url = rate_limit::write(esa, || {
+                esa.upload_attachment(name, content_type(&image), content.clone())
+            })
+            .await?;
+            println!("uploaded {}", image);
+            state.uploads.insert(image, url);
+            save(&state)?;
+        }
+        let uploads = state.uploads.clone().into_iter().collect();
+        let body = rewrite_links(source, &source.body, &uploads, &files);
+
+        let content = PostContent {
+            name: source.title.clone(),
+            full_name: source.title.clone(),
+            body_md: Some(body),
+            tags: source.tags.clone(),
+            category: source.category(category_root),
+        };
+        let message = match source.date {
+            Some(ref date) => format!("imported from {} ({})", source.path, date),
+            None => format!("imported from {}", source.path),
+        };
+        let found = if state.pending.as_ref() == Some(&source.path) {
+            restore::find_created(esa, &content, &message).await?
+        } else {
+            None
+        };
+        // 作成と進捗の保存の間で中断すると二重に作らないように、作成前に印を残す
+        let (number, full_name) = match found {
+            Some(found) => (found.number, found.full_name),
+            None => {
+                state.pending = Some(source.path.clone());
+                save(&state)?;
+                let created = rate_limit::write(esa, || {
+                    esa.create_post(content.clone(), source.wip, Some(message.clone()))
+                })
+                .await?;
+                (created.number, created.full_name)
+            }
+        };
+        println!("{} -> #{} {}", source.path, number, full_name);
+        state.posts.insert(
+            source.path.clone(),
+            Imported {
+                number,
+                links_updated: false,
+            },
+        );
+        state.pending = None;
+        save(&state)?;
+    }
+
+    let urls = state
+        .posts
+        .iter()
+        .map(|(path, imported)| (path.clone(), format!("/posts/{}", imported.number)))
+        .chain(state.uploads.clone())
+        .collect::<HashMap<_, _>>();
+    for source in &sources {
+        let number = match state.posts.get(&source.path) {
+            Some(imported) if !imported.links_updated => imported.number,
+            _ => continue,
+        };
+        let body = rewrite_links(source, &source.body, &urls, &files);
+        rate_limit::write(esa, || {
+            restore::update_links(esa, number, body.clone(), "update links to imported posts")
+        })
+        .await?;
+        if let Some(imported) = state.posts.get_mut(&source.path) {
+            imported.links_updated = true;
+        }
+        save(&state)?;
+    }
+
+    println!(
+        "imported {} files into {}. progress is kept in {}",
+        state.posts.len(),
+        state.team,
+        state_path.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let source = SourceFile::parse(
+            "手順書/デプロイ.md",
+            "---\ntitle: リリース手順\ntags: [deploy, \"#ops\"]\ndate: 2021-04-01\ndraft: true\n---\n本文\n",
+        )
+        .unwrap();
+        assert_eq!(
+            SourceFile {
+                path: String::from("手順書/デプロイ.md"),
+                title: String::from("リリース手順"),
+                tags: vec![String::from("deploy"), String::from("ops")],
+                wip: true,
+                date: Some(String::from("2021-04-01")),
+                body: String::from("本文\n"),
+            },
+            source
+        );
+        assert_eq!(
+            Some(String::from("Imported/手順書")),
+            source.category(Some("Imported/"))
+        );
+
+        let source =
+            SourceFile::parse("memo.md", "---\ntags: a, b\npublished: true\n---\n").unwrap();
+        assert_eq!("memo", source.title);
+        assert_eq!(vec![String::from("a"), String::from("b")], source.tags);
+        assert!(!source.wip);
+        assert_eq!(None, source.category(None));
+    }
+
+    #[test]
+    fn test_rewrite_links() {
+        let files = ["docs/a.md", "docs/b.md", "img/x.png"].map(String::from);
+        let source = SourceFile::parse(
+            "docs/a.md",
+            "[b](b.md#usage) [web](https://example.com/b.md) [[b|B]] ![[x.png]] ![x](../img/x.png) [[none]] \
+             ![y](data:image/png;base64,AA) ![z](../../z.png) ![[gone.png]] ![w](https://example.com/w.png)",
+        )
+        .unwrap();
+        assert_eq!(
+            (
+                vec![String::from("img/x.png")],
+                vec![
+                    String::from("../../z.png"),
+                    String::from("data:image/png;base64,AA"),
+                    String::from("gone.png")
+                ]
+            ),
+            local_images(&source, &files)
+        );
+
+        let urls = [
+            ("docs/b.md", "/posts/2"),
+            ("img/x.png", "https://files.esa.io/x.png"),
+        ]
+        .into_iter()
+        .map(|(path, url)| (path.to_string(), url.to_string()))
+        .collect();
+        assert_eq!(
+            "[b](/posts/2#usage) [web](https://example.com/b.md) [B](/posts/2) ![x.png](https://files.esa.io/x.png) ![x](https://files.esa.io/x.png) [[none]] \
+             ![y](data:image/png;base64,AA) ![z](../../z.png) ![[gone.png]] ![w](https://example.com/w.png)",
+            rewrite_links(&source, &source.body, &urls, &files)
+        );
+    }
+}
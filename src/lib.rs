@@ -1,17 +1,42 @@
 use std::env;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitStatus;
 
 use anyhow::{bail, Context, Result};
-use clap::Parser;
+use chrono::Local;
+use clap::{CommandFactory, Parser};
+use clap_complete::Shell;
 
-use esa::{self, Esa};
+use esa::{self, Esa, Team};
+use serde_json::Value;
 
+mod assets;
+mod backup;
+mod cache;
+mod completion;
 mod config;
+mod conflict;
+mod diff;
+mod docs;
+mod export;
+mod hooks;
+mod import;
+mod paging;
+mod post_meta;
+mod post_template;
+mod rate_limit;
+mod restore;
+mod sync;
+mod template;
 mod tmp_file;
+mod watch;
+mod workspace;
 
-use config::{Config, Env};
-use tmp_file::Editor;
+use config::{Config, ConfigFile, Env, TeamConfig};
+use template::Template;
+use tmp_file::{Draft, Editor, EditorCommand};
 
 #[derive(Parser, Debug)]
 #[clap(
@@ -25,7 +50,7 @@ struct Opts {
     sub: SubCmd,
 
     /// Uses team ID
-    #[clap(short, long)]
+    #[clap(short, long, global = true)]
     team: Option<String>,
 }
 
@@ -36,7 +61,7 @@ enum SubCmd {
     Team,
 
     /// Shows or edits posts
-    #[clap(name = "post")]
+    #[clap(name = "post", after_long_help = tmp_file::EDITOR_FORMAT_HELP)]
     Post {
         /// Post ID
         #[clap(name = "ID")]
@@ -77,6 +102,501 @@ enum SubCmd {
         /// Sets desc(default) | asc
         #[clap(short, long)]
         order: Option<String>,
+
+        /// Prints posts in the given format.
+        /// e.g. '{number}\t{category}/{name}\t{updated_by.screen_name}\t{updated_at:%Y-%m-%d}'
+        #[clap(long)]
+        format: Option<String>,
+
+        #[clap(flatten)]
+        save: SaveOpts,
+
+        #[clap(subcommand)]
+        cmd: Option<PostCmd>,
+    },
+
+    /// Pulls posts into the directory as Markdown files with front matter
+    #[clap(name = "pull")]
+    Pull {
+        /// Directory mirroring categories
+        #[clap(name = "DIR", parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Pulls posts in the category and its subcategories
+        #[clap(short, long, conflicts_with = "query")]
+        category: Option<String>,
+
+        /// Pulls posts matching the query.
+        /// see details https://docs.esa.io/posts/104
+        #[clap(short, long)]
+        query: Option<String>,
+
+        /// Downloads attached files into `assets/` and links to the local copies
+        #[clap(long)]
+        attachments: bool,
+    },
+
+    /// Pushes files changed in the pulled directory to esa.
+    /// Files without `number` in the front matter are created as new posts
+    #[clap(name = "push")]
+    Push {
+        /// Files or directories. Defaults to the current directory
+        #[clap(name = "PATH", parse(from_os_str))]
+        paths: Vec<PathBuf>,
+
+        /// Shows what would be pushed without sending
+        #[clap(long)]
+        dry_run: bool,
+
+        /// Change message
+        #[clap(short, long)]
+        message: Option<String>,
+
+        /// Skips the built-in checks and the hooks in the config file
+        #[clap(long)]
+        no_verify: bool,
+    },
+
+    /// Shows files changed locally and posts updated on esa since pull
+    #[clap(name = "status")]
+    Status {
+        /// Pulled directory. Defaults to the current directory
+        #[clap(name = "DIR", parse(from_os_str))]
+        dir: Option<PathBuf>,
+    },
+
+    /// Shows diffs of local files against the posts on esa
+    #[clap(name = "diff")]
+    Diff {
+        /// Files or directories. Defaults to files changed in the current directory
+        #[clap(name = "PATH", parse(from_os_str))]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Watches the pulled directory and pushes files when they are saved
+    #[clap(name = "watch")]
+    Watch {
+        /// Pulled directory. Defaults to the current directory
+        #[clap(name = "DIR", parse(from_os_str), default_value = ".")]
+        dir: PathBuf,
+
+        /// Milliseconds to wait for saves to settle before pushing
+        #[clap(long, default_value = "1000")]
+        debounce: u64,
+
+        /// Change message
+        #[clap(short, long)]
+        message: Option<String>,
+
+        /// Skips the built-in checks and the hooks in the config file
+        #[clap(long)]
+        no_verify: bool,
+    },
+
+    /// Backs up all posts with comments and stargazers.
+    /// Updates the existing backup with posts updated since the last run
+    #[clap(name = "backup")]
+    Backup {
+        /// Directory or .tar.zst file
+        #[clap(name = "TARGET", parse(from_os_str))]
+        target: PathBuf,
+
+        /// Fetches all posts again
+        #[clap(long)]
+        full: bool,
+
+        /// Downloads attached files into `assets/` and links to the local copies
+        #[clap(long)]
+        attachments: bool,
+    },
+
+    /// Restores posts in the backup into the team given by --team.
+    /// Running again resumes an interrupted restore
+    #[clap(name = "restore")]
+    Restore {
+        /// Directory or .tar.zst file made by backup
+        #[clap(name = "TARGET", parse(from_os_str))]
+        target: PathBuf,
+
+        /// Re-creates comments as quoted text
+        #[clap(long)]
+        comments: bool,
+    },
+
+    /// Imports Markdown files in a directory like an Obsidian vault or Hugo content as posts.
+    /// Running again resumes an interrupted import
+    #[clap(name = "import")]
+    Import {
+        /// Directory of Markdown files
+        #[clap(name = "DIR", parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Category under which the directory tree is created
+        #[clap(long, value_name = "CATEGORY")]
+        category_root: Option<String>,
+    },
+
+    /// Fetches posts updated since the last sync into the local cache
+    #[clap(name = "sync")]
+    Sync {
+        /// Fetches all posts and forgets deleted posts
+        #[clap(long)]
+        full: bool,
+    },
+
+    /// Searches posts. Posts fetched by any command are cached for --offline
+    #[clap(name = "search")]
+    Search {
+        /// Words or a query.
+        /// see details https://docs.esa.io/posts/104
+        #[clap(name = "QUERY", required = true)]
+        query: Vec<String>,
+
+        /// Searches the full text of the cached posts without the network.
+        /// Posts containing all the words are printed
+        #[clap(long)]
+        offline: bool,
+
+        /// Prints posts in the given format like `post --format`
+        #[clap(long)]
+        format: Option<String>,
+    },
+
+    /// Exports posts for reading without esa
+    #[clap(name = "export", subcommand)]
+    Export(ExportCmd),
+
+    /// Shows or changes the config file
+    #[clap(name = "config", subcommand)]
+    Config(ConfigCmd),
+
+    /// Lists, resumes or discards drafts kept after failing to save a post
+    #[clap(name = "draft", subcommand)]
+    Draft(DraftCmd),
+
+    /// Prints the shell completion script
+    #[clap(name = "completions")]
+    Completions {
+        /// Target shell
+        #[clap(value_enum)]
+        shell: Shell,
+    },
+
+    /// Generates man pages and the Markdown command reference
+    #[clap(name = "generate-docs", hide = true)]
+    GenerateDocs {
+        /// Output directory
+        #[clap(name = "DIR", parse(from_os_str))]
+        dir: PathBuf,
+    },
+
+    /// Prints dynamic completion candidates
+    #[clap(name = completion::COMPLETE_SUBCOMMAND, hide = true)]
+    Complete {
+        #[clap(value_enum)]
+        kind: completion::Kind,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum PostCmd {
+    /// Creates new post.
+    /// Opens the editor unless FILE, --body or --body-file is given
+    #[clap(name = "new")]
+    New(Box<NewPostOpts>),
+
+    /// Moves posts to the category. `/` moves them to the top
+    #[clap(name = "mv")]
+    Mv {
+        /// Post IDs
+        #[clap(name = "ID", required = true)]
+        ids: Vec<i32>,
+
+        /// New category
+        #[clap(name = "CATEGORY")]
+        category: String,
+
+        /// Change message
+        #[clap(short, long)]
+        message: Option<String>,
+    },
+
+    /// Changes the title of the post
+    #[clap(name = "rename")]
+    Rename {
+        /// Post ID
+        #[clap(name = "ID")]
+        id: i32,
+
+        /// New title
+        #[clap(name = "TITLE")]
+        title: String,
+
+        /// Change message
+        #[clap(short, long)]
+        message: Option<String>,
+    },
+
+    /// Adds or removes tags of posts. e.g. `post tag 12 34 +foo -bar`
+    #[clap(name = "tag")]
+    Tag {
+        /// Post IDs followed by +TAG to add or -TAG to remove.
+        /// -m and --message are taken as options. Use --remove to remove tags with those names
+        #[clap(name = "ID_OR_TAG", required = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+
+        /// Tag to remove. Repeat to remove multiple tags
+        #[clap(
+            short,
+            long = "remove",
+            value_name = "TAG",
+            multiple_occurrences = true
+        )]
+        removes: Vec<String>,
+
+        /// Change message
+        #[clap(short, long)]
+        message: Option<String>,
+    },
+}
+
+#[derive(clap::Args, Debug)]
+struct NewPostOpts {
+    /// Markdown file with optional front matter. `-` reads stdin
+    #[clap(name = "FILE", parse(from_os_str), conflicts_with_all = &["body", "body-file"])]
+    file: Option<PathBuf>,
+
+    /// Title. Overrides the front matter
+    #[clap(long)]
+    title: Option<String>,
+
+    /// Category. Overrides the front matter
+    #[clap(long)]
+    category: Option<String>,
+
+    /// Tag. Repeat to set multiple tags. Overrides the front matter
+    #[clap(long = "tag", value_name = "TAG", multiple_occurrences = true)]
+    tags: Vec<String>,
+
+    /// Body. `-` reads stdin
+    #[clap(long, conflicts_with = "body-file")]
+    body: Option<String>,
+
+    /// File of the body
+    #[clap(long, parse(from_os_str))]
+    body_file: Option<PathBuf>,
+
+    /// Template post ID or name like `日報` or `Templates/日報`.
+    /// Expands %{Year}, %{year}, %{month}, %{day}, %{week} and %{me} and opens the editor
+    #[clap(long, conflicts_with_all = &["FILE", "body", "body-file"])]
+    template: Option<String>,
+
+    #[clap(flatten)]
+    save: SaveOpts,
+}
+
+/// Options applied when creating or editing a post
+#[derive(clap::Args, Debug)]
+struct SaveOpts {
+    /// Saves the post as WIP
+    #[clap(long, conflicts_with = "ship")]
+    wip: bool,
+
+    /// Ships the post. Editing keeps the current state without --wip or --ship
+    #[clap(long)]
+    ship: bool,
+
+    /// Change message
+    #[clap(short, long)]
+    message: Option<String>,
+
+    /// Saves the edit without showing the diff and confirmation
+    #[clap(short, long)]
+    yes: bool,
+
+    /// Skips the built-in checks and the hooks in the config file
+    #[clap(long)]
+    no_verify: bool,
+}
+
+impl NewPostOpts {
+    /// Whether the post is given without the editor
+    fn has_source(&self) -> bool {
+        self.file.is_some() || self.body.is_some() || self.body_file.is_some()
+    }
+
+    /// Fields given on the command line take precedence over the front matter
+    fn apply(&mut self, front_matter: &mut tmp_file::FrontMatter) {
+        if let Some(title) = self.title.take() {
+            front_matter.title = Some(title);
+        }
+        if let Some(category) = self.category.take() {
+            front_matter.category = Some(category);
+        }
+        if !self.tags.is_empty() {
+            front_matter.tags = std::mem::take(&mut self.tags);
+        }
+    }
+
+    /// Text opened in the editor with the fields given on the command line filled in
+    fn editor_content(&mut self) -> String {
+        if self.title.is_none() && self.category.is_none() && self.tags.is_empty() {
+            return tmp_file::TMP_FILE_DEFAULT_VALUE.to_string();
+        }
+        let mut front_matter = tmp_file::FrontMatter {
+            wip: Some(true),
+            ..Default::default()
+        };
+        self.apply(&mut front_matter);
+        front_matter.wip = self.save.wip().or(front_matter.wip);
+        front_matter.message = self.save.message.clone();
+        tmp_file::format_post_content(&front_matter, "")
+    }
+}
+
+impl SaveOpts {
+    fn wip(&self) -> Option<bool> {
+        match (self.wip, self.ship) {
+            (true, _) => Some(true),
+            (_, true) => Some(false),
+            _ => None,
+        }
+    }
+
+    /// Options given on the command line take precedence over the editor buffer
+    fn apply(&self, parsed: &mut tmp_file::ParsedPost) {
+        if let Some(wip) = self.wip() {
+            parsed.wip = Some(wip);
+        }
+        if let Some(ref message) = self.message {
+            parsed.message = Some(message.clone());
+        }
+    }
+}
+
+#[derive(Parser, Debug)]
+enum ExportCmd {
+    /// Writes a static HTML site with category and tag indexes and a search box.
+    /// Links between the exported posts point to the local pages
+    #[clap(name = "html")]
+    Html {
+        /// Directory to write into
+        #[clap(name = "DIR", parse(from_os_str))]
+        dir: PathBuf,
+
+        /// Exports posts matching the query.
+        /// see details https://docs.esa.io/posts/104
+        #[clap(short, long)]
+        query: Option<String>,
+
+        /// Downloads attached files into `assets/` and links to the local copies
+        #[clap(long)]
+        attachments: bool,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum ConfigCmd {
+    /// Adds, removes or lists teams
+    #[clap(name = "team", subcommand)]
+    Team(ConfigTeamCmd),
+
+    /// Prints the value of the key
+    #[clap(name = "get")]
+    Get {
+        /// e.g. editor | hooks
+        #[clap(name = "KEY")]
+        key: String,
+    },
+
+    /// Sets the value of the key
+    #[clap(name = "set")]
+    Set {
+        /// e.g. editor | hooks
+        #[clap(name = "KEY")]
+        key: String,
+
+        #[clap(name = "VALUE")]
+        value: String,
+
+        /// Parses the value as JSON like '[{"command": "textlint"}]'
+        #[clap(long)]
+        json: bool,
+    },
+
+    /// Prints the path of the config file
+    #[clap(name = "path")]
+    Path,
+}
+
+#[derive(Parser, Debug)]
+enum ConfigTeamCmd {
+    /// Adds the team. The access token is read from piped stdin unless its source is given
+    #[clap(name = "add")]
+    Add {
+        /// Team ID, the subdomain of esa.io
+        #[clap(name = "TEAM")]
+        id: String,
+
+        /// Command printing the access token like 'pass show esa/work'
+        #[clap(long, value_name = "COMMAND", conflicts_with_all = &["token-env", "token-file"])]
+        token_command: Option<String>,
+
+        /// Environment variable holding the access token
+        #[clap(long, value_name = "NAME", conflicts_with = "token-file")]
+        token_env: Option<String>,
+
+        /// File containing the access token
+        #[clap(long, value_name = "PATH")]
+        token_file: Option<String>,
+
+        /// Makes the team the default team
+        #[clap(long)]
+        default: bool,
+    },
+
+    /// Removes the team
+    #[clap(name = "remove")]
+    Remove {
+        #[clap(name = "TEAM")]
+        id: String,
+    },
+
+    /// Lists teams. The default team is marked with `*`
+    #[clap(name = "list")]
+    List,
+
+    /// Makes the team the default team
+    #[clap(name = "set-default")]
+    SetDefault {
+        #[clap(name = "TEAM")]
+        id: String,
+    },
+}
+
+#[derive(Parser, Debug)]
+enum DraftCmd {
+    /// Lists drafts
+    #[clap(name = "list")]
+    List,
+
+    /// Reopens the draft in the editor and saves it
+    #[clap(name = "resume")]
+    Resume {
+        /// Draft name shown by `draft list`
+        #[clap(name = "NAME")]
+        name: String,
+
+        #[clap(flatten)]
+        save: SaveOpts,
+    },
+
+    /// Deletes the draft
+    #[clap(name = "discard")]
+    Discard {
+        /// Draft name shown by `draft list`
+        #[clap(name = "NAME")]
+        name: String,
     },
 }
 
@@ -84,18 +604,41 @@ pub async fn run() -> Result<()> {
     let opts = Opts::parse();
     log::debug!("Options: {:?}", opts);
 
+    if let SubCmd::Completions { shell } = opts.sub {
+        completion::generate(shell, &mut Opts::command(), &mut io::stdout())?;
+        return Ok(());
+    }
+    if let SubCmd::GenerateDocs { dir } = opts.sub {
+        docs::generate(Opts::command(), &dir)?;
+        println!("generated docs in {}", dir.display());
+        return Ok(());
+    }
+
     let esa_env = Env::new(env::var("ESA_CONFIG").ok().map(PathBuf::from));
     log::debug!("Env: {:?}", esa_env);
 
+    if let SubCmd::Config(cmd) = opts.sub {
+        return run_config_cmd(&esa_env, cmd);
+    }
+
+    let config = Config::new(&esa_env)?;
+    log::debug!("Config: {:?}", config);
+
+    if let SubCmd::Complete { kind } = opts.sub {
+        let team = find_team(&config, opts.team).ok();
+        return completion::print_candidates(kind, &esa_env, &config, team).await;
+    }
+
+    if let SubCmd::Draft(cmd) = opts.sub {
+        return match cmd {
+            DraftCmd::List => print_drafts(&esa_env),
+            DraftCmd::Resume { name, save } => resume_draft(&config, &esa_env, &name, &save).await,
+            DraftCmd::Discard { name } => discard_draft(&esa_env, &name),
+        };
+    }
+
     let esa = {
-        let config = Config::new(&esa_env);
-        let team = match opts.team {
-            Some(team_id) => config.get(esa::TeamId::new(team_id)),
-            None => config.default(),
-        }
-        .context("no team is available")?
-        .clone();
-        log::debug!("Config: {:?}", config);
+        let team = select_team(&config, opts.team)?;
         log::debug!("Team: {:?}", team);
 
         Esa::new(team)
@@ -115,31 +658,266 @@ pub async fn run() -> Result<()> {
             include,
             sort,
             order,
-        } => match id {
-            Some(id) => {
+            format,
+            save,
+            cmd,
+        } => match (cmd, id) {
+            (Some(PostCmd::New(mut new_post)), _) => {
+                if new_post.has_source() {
+                    create_post_from_source(&esa, &esa_env, &config, *new_post).await?;
+                } else if new_post.template.is_some() {
+                    create_post_from_template(&esa, &esa_env, &config, *new_post).await?;
+                } else {
+                    let content = new_post.editor_content();
+                    create_post(&esa, &esa_env, &config, &content, &new_post.save).await?;
+                }
+            }
+            (
+                Some(PostCmd::Mv {
+                    ids,
+                    category,
+                    message,
+                }),
+                _,
+            ) => {
+                let category = post_meta::normalize_category(&category);
+                edit_metadata(&esa, &ids, message, |post| {
+                    (post.name.clone(), post.tags.clone(), category.clone())
+                })
+                .await?;
+            }
+            (Some(PostCmd::Rename { id, title, message }), _) => {
+                let title = title.trim().to_string();
+                if title.is_empty() {
+                    bail!("title must not be empty");
+                }
+                edit_metadata(&esa, &[id], message, |post| {
+                    (title.clone(), post.tags.clone(), post.category.clone())
+                })
+                .await?;
+            }
+            (
+                Some(PostCmd::Tag {
+                    args,
+                    removes,
+                    message,
+                }),
+                _,
+            ) => {
+                let tag_args = post_meta::parse_tag_args(&args, &removes)?;
+                let message = tag_args.message.or(message);
+                edit_metadata(&esa, &tag_args.ids, message, |post| {
+                    let tags = post_meta::apply_tag_changes(&post.tags, &tag_args.changes);
+                    (post.name.clone(), tags, post.category.clone())
+                })
+                .await?;
+            }
+            (None, Some(id)) => {
                 if edit {
-                    edit_post(&esa, id, &esa_env).await?;
+                    edit_post(&esa, id, &esa_env, &config, &save).await?;
                 } else if delete {
                     delete_post(&esa, id).await?;
                 } else {
-                    print_post(&esa, id).await?;
+                    print_post(&esa, &esa_env, id, format).await?;
                 }
             }
-            None => {
+            (None, None) => {
                 if list {
-                    print_posts(&esa, query, include, sort, order).await?;
+                    print_posts(&esa, &esa_env, query, include, sort, order, format).await?;
                 } else if new {
-                    create_post(&esa, &esa_env).await?;
+                    create_post(
+                        &esa,
+                        &esa_env,
+                        &config,
+                        tmp_file::TMP_FILE_DEFAULT_VALUE,
+                        &save,
+                    )
+                    .await?;
                 } else {
                     bail!("Post ID argument or --list option are required.");
                 }
             }
         },
+        SubCmd::Pull {
+            dir,
+            category,
+            query,
+            attachments,
+        } => {
+            let query = match category {
+                Some(category) => Some(format!("in:\"{}\"", category.trim_matches('/'))),
+                None => query,
+            };
+            sync::pull(&esa, &esa_env, &dir, query, attachments).await?;
+        }
+        SubCmd::Push {
+            paths,
+            dry_run,
+            message,
+            no_verify,
+        } => {
+            let options = sync::PushOptions {
+                dry_run,
+                message,
+                verify: !no_verify,
+            };
+            sync::push(&esa, &config, &paths, &options).await?;
+        }
+        SubCmd::Watch {
+            dir,
+            debounce,
+            message,
+            no_verify,
+        } => {
+            let options = sync::PushOptions {
+                dry_run: false,
+                message,
+                verify: !no_verify,
+            };
+            let debounce = std::time::Duration::from_millis(debounce);
+            watch::watch(&esa, &config, &dir, &options, debounce).await?;
+        }
+        SubCmd::Backup {
+            target,
+            full,
+            attachments,
+        } => {
+            backup::backup(&esa, &esa_env, &target, full, attachments).await?;
+        }
+        SubCmd::Restore { target, comments } => {
+            restore::restore(&esa, &esa_env, &target, comments).await?;
+        }
+        SubCmd::Import { dir, category_root } => {
+            import::import(&esa, &dir, category_root.as_deref()).await?;
+        }
+        SubCmd::Sync { full } => {
+            cache::sync(&esa, &esa_env, full).await?;
+        }
+        SubCmd::Search {
+            query,
+            offline,
+            format,
+        } => {
+            let query = query.join(" ");
+            if offline {
+                print_cached_posts(&esa, &esa_env, &query, format)?;
+            } else {
+                print_posts(&esa, &esa_env, Some(query), None, None, None, format).await?;
+            }
+        }
+        SubCmd::Export(ExportCmd::Html {
+            dir,
+            query,
+            attachments,
+        }) => {
+            export::export_html(&esa, &esa_env, &dir, query, attachments).await?;
+        }
+        SubCmd::Status { dir } => {
+            sync::status(&esa, dir).await?;
+        }
+        SubCmd::Diff { paths } => {
+            sync::diff(&esa, &paths).await?;
+        }
+        SubCmd::Draft(_)
+        | SubCmd::Config(_)
+        | SubCmd::Completions { .. }
+        | SubCmd::GenerateDocs { .. }
+        | SubCmd::Complete { .. } => {
+            unreachable!("handled before selecting a team")
+        }
     }
 
     Ok(())
 }
 
+/// Selects the team given by `--team` or the default team
+fn select_team(config: &Config, team_id: Option<String>) -> Result<Team> {
+    find_team(config, team_id)?.resolve()
+}
+
+/// Team given by `--team` or the default team before reading its access token
+fn find_team(config: &Config, team_id: Option<String>) -> Result<&TeamConfig> {
+    match team_id {
+        Some(team_id) => config.get(esa::TeamId::new(team_id)),
+        None => config.default(),
+    }
+    .context("no team is available")
+}
+
+/// Show or change the config file
+fn run_config_cmd(esa_env: &Env, cmd: ConfigCmd) -> Result<()> {
+    let mut config = ConfigFile::load(esa_env)?;
+    match cmd {
+        ConfigCmd::Path => {
+            println!("{}", esa_env.config_file_path.display());
+            return Ok(());
+        }
+        ConfigCmd::Get { key } => {
+            match config.get(&key)? {
+                Some(Value::String(value)) => println!("{}", value),
+                Some(value) => println!("{}", serde_json::to_string_pretty(value)?),
+                None => bail!("{} is not set", key),
+            }
+            return Ok(());
+        }
+        ConfigCmd::Set { key, value, json } => {
+            let value = if json {
+                serde_json::from_str(&value).context("invalid JSON")?
+            } else {
+                Value::String(value)
+            };
+            config.set(&key, value)?;
+        }
+        ConfigCmd::Team(ConfigTeamCmd::List) => {
+            let (default, teams) = config.team_ids();
+            if let Some(id) = default {
+                println!("* {}", id);
+            }
+            for id in teams {
+                println!("  {}", id);
+            }
+            return Ok(());
+        }
+        ConfigCmd::Team(ConfigTeamCmd::Add {
+            id,
+            token_command,
+            token_env,
+            token_file,
+            default,
+        }) => {
+            let source = match (token_command, token_env, token_file) {
+                (Some(command), _, _) => ("access_token_command", command),
+                (_, Some(name), _) => ("access_token_env", name),
+                (_, _, Some(path)) => ("access_token_file", path),
+                _ => {
+                    // 端末で入力すると画面に残るので、パイプで渡されたときだけ読む
+                    if io::stdin().is_terminal() {
+                        bail!(
+                            "pipe the access token into stdin, or give --token-command, --token-env or --token-file"
+                        );
+                    }
+                    let mut access_token = String::new();
+                    io::stdin().read_line(&mut access_token)?;
+                    let access_token = access_token.trim().to_string();
+                    if access_token.is_empty() {
+                        bail!("access token is empty");
+                    }
+                    ("access_token", access_token)
+                }
+            };
+            let team = [(source.0.to_string(), Value::String(source.1))]
+                .into_iter()
+                .collect();
+            config.add_team(&id, team, default)?;
+        }
+        ConfigCmd::Team(ConfigTeamCmd::Remove { id }) => config.remove_team(&id)?,
+        ConfigCmd::Team(ConfigTeamCmd::SetDefault { id }) => config.set_default_team(&id)?,
+    }
+    config.save()?;
+    println!("updated {}", esa_env.config_file_path.display());
+    Ok(())
+}
+
 /// Print team
 async fn print_team(esa: &Esa) -> Result<()> {
     let team = esa.team().await?;
@@ -150,8 +928,19 @@ async fn print_team(esa: &Esa) -> Result<()> {
 /// Print post
 /// # Args
 /// - id: Post ID
-async fn print_post(esa: &Esa, id: i32) -> Result<()> {
+/// - format: Output template
+async fn print_post(esa: &Esa, esa_env: &Env, id: i32, format: Option<String>) -> Result<()> {
+    let template = format.as_deref().map(Template::parse).transpose()?;
     let post = esa.post(id).await?;
+    cache::remember(
+        esa_env,
+        &esa.team_id().to_string(),
+        std::slice::from_ref(&post),
+    );
+    if let Some(template) = template {
+        println!("{}", template.render(&post)?);
+        return Ok(());
+    }
     println!("{}", post.url);
     println!("{}", post.full_name);
     println!("{}", post.body_md);
@@ -161,11 +950,14 @@ async fn print_post(esa: &Esa, id: i32) -> Result<()> {
 /// Print posts
 async fn print_posts(
     esa: &Esa,
+    esa_env: &Env,
     query: Option<String>,
     include: Option<Vec<String>>,
     sort: Option<String>,
     order: Option<String>,
+    format: Option<String>,
 ) -> Result<()> {
+    let template = format.as_deref().map(Template::parse).transpose()?;
     let include: Option<Vec<esa::post::Include>> =
         include.map(|include| include.into_iter().map(|i| i.into()).collect());
     let sort = sort.map(|s| esa::post::Sort::from((s, order)));
@@ -173,62 +965,516 @@ async fn print_posts(
     log::debug!("{:?}", &search_query);
 
     let posts = esa.posts(search_query).await?;
-    for post in posts.posts {
-        println!("{}\t{}", post.number, post.full_name);
+    cache::remember(esa_env, &esa.team_id().to_string(), &posts.posts);
+    for post in &posts.posts {
+        match template {
+            Some(ref template) => println!("{}", template.render(post)?),
+            None => println!("{}\t{}", post.number, post.full_name),
+        }
+    }
+    Ok(())
+}
+
+/// Print cached posts containing all words in the query
+fn print_cached_posts(esa: &Esa, esa_env: &Env, query: &str, format: Option<String>) -> Result<()> {
+    let template = format.as_deref().map(Template::parse).transpose()?;
+    let cache = cache::PostCache::open(esa_env, &esa.team_id().to_string())?;
+    if cache.len()? == 0 {
+        bail!("no post is cached. run `esa-cli sync` first");
+    }
+    for post in cache.search(query)? {
+        match template {
+            Some(ref template) => println!("{}", template.render(&post)?),
+            None => println!("{}\t{}", post.number, post.full_name),
+        }
     }
     Ok(())
 }
 
 /// Create new post
-async fn create_post(esa: &Esa, esa_env: &Env) -> Result<()> {
-    let editor = Editor::new(esa_env);
-    let exit_status = editor.open(tmp_file::TMP_FILE_DEFAULT_VALUE);
-    if exit_status.success() {
-        if let Some(diff) = editor.diff() {
-            let post_content = tmp_file::parse_post(&diff)?;
-            let created = esa.create_post(post_content, true, None).await?;
-            println!("Create new post! {}", created.url);
-        } else {
-            println!("creating new post is canceled");
+/// # Args
+/// - content: Text opened in the editor
+async fn create_post(
+    esa: &Esa,
+    esa_env: &Env,
+    config: &Config,
+    content: &str,
+    save: &SaveOpts,
+) -> Result<()> {
+    let editor_command = EditorCommand::resolve(config)?;
+    let draft = Draft::create(esa_env, esa.team_id(), None)?;
+    let exit_status = Editor::new(&editor_command, &draft).open(content)?;
+    let Some(draft) = editor_succeeded(draft, exit_status, Some(content), NEW_POST_ABORTED)? else {
+        return Ok(());
+    };
+    save_new_post(esa, config, draft, save).await
+}
+
+/// Create new post from the template post
+/// # Args
+/// - new_post: --template and the fields overriding the template
+async fn create_post_from_template(
+    esa: &Esa,
+    esa_env: &Env,
+    config: &Config,
+    mut new_post: NewPostOpts,
+) -> Result<()> {
+    let template = match new_post.template {
+        Some(ref template) => find_template(esa, template).await?,
+        None => bail!("--template is required"),
+    };
+    let user = esa.user().await?;
+    let variables = post_template::Variables {
+        now: Local::now(),
+        me: &user.screen_name,
+    };
+    let (mut front_matter, body) = post_template::new_post(&template, &variables);
+    new_post.apply(&mut front_matter);
+    front_matter.wip = new_post.save.wip().or(front_matter.wip);
+    front_matter.message = new_post.save.message.clone();
+    let content = tmp_file::format_post_content(&front_matter, &body);
+    create_post(esa, esa_env, config, &content, &new_post.save).await
+}
+
+/// Finds the template post by ID or name.
+/// A name without category is searched in `Templates`
+async fn find_template(esa: &Esa, template: &str) -> Result<esa::post::Post> {
+    if let Ok(id) = template.parse::<i32>() {
+        return Ok(esa.post(id).await?);
+    }
+    let (category, name) = match template.rsplit_once('/') {
+        Some((category, name)) => (category, name),
+        None => (post_template::TEMPLATES_CATEGORY, template),
+    };
+    let query = format!("in:\"{}\" title:\"{}\"", category, name);
+    let search_query = esa::post::SearchQuery::new(Some(query), None, None);
+    esa.posts(search_query)
+        .await?
+        .posts
+        .into_iter()
+        .find(|post| post.name == name && post.category.as_deref() == Some(category))
+        .with_context(|| format!("template '{}' is not found", template))
+}
+
+/// Create new post without the editor
+/// # Args
+/// - new_post: FILE, --body or --body-file and the fields overriding the front matter
+async fn create_post_from_source(
+    esa: &Esa,
+    esa_env: &Env,
+    config: &Config,
+    mut new_post: NewPostOpts,
+) -> Result<()> {
+    let (mut front_matter, body) = match new_post.file {
+        Some(ref path) => {
+            let content = read_source(path)?;
+            tmp_file::split_front_matter(&content)
+                .with_context(|| format!("invalid front matter in {}", path.display()))?
+        }
+        None => {
+            let body = match (new_post.body.take(), new_post.body_file.take()) {
+                (Some(body), _) if body == "-" => read_source(Path::new("-"))?,
+                (Some(body), _) => body,
+                (None, Some(path)) => read_source(&path)?,
+                (None, None) => String::new(),
+            };
+            (tmp_file::FrontMatter::default(), body)
         }
+    };
+    new_post.apply(&mut front_matter);
+    if front_matter.title.is_none() {
+        bail!("title is required. Set --title or `title` in the front matter");
+    }
+
+    // hooks には editor と同じ形式のファイルを渡す
+    let content = tmp_file::format_post_content(&front_matter, &body);
+    let mut parsed = tmp_file::post_from_front_matter(front_matter, body)
+        .map_err(|e| anyhow::anyhow!(e.message))?;
+    new_post.save.apply(&mut parsed);
+    if !new_post.save.no_verify {
+        let draft = Draft::create(esa_env, esa.team_id(), None)?;
+        let verified = draft
+            .write(&content)
+            .and_then(|_| hooks::verify(config, &parsed, &draft.path));
+        draft.discard()?;
+        verified?;
+    }
+    let created = esa
+        .create_post(parsed.content, parsed.wip.unwrap_or(true), parsed.message)
+        .await?;
+    println!("Create new post! {}", created.url);
+    Ok(())
+}
+
+/// Reads the file. `-` reads stdin
+fn read_source(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        let mut content = String::new();
+        io::stdin()
+            .read_to_string(&mut content)
+            .context("failed to read stdin")?;
+        return Ok(content);
+    }
+    fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Create new post from the draft closed in the editor
+async fn save_new_post(esa: &Esa, config: &Config, draft: Draft, save: &SaveOpts) -> Result<()> {
+    if let Some(diff) = draft.diff()? {
+        let result = async {
+            let mut parsed = tmp_file::parse_post(&diff)?;
+            save.apply(&mut parsed);
+            if !save.no_verify {
+                hooks::verify(config, &parsed, &draft.path)?;
+            }
+            esa.create_post(parsed.content, parsed.wip.unwrap_or(true), parsed.message)
+                .await
+                .map_err(anyhow::Error::from)
+        }
+        .await;
+        let created = keep_draft_on_error(result, &draft)?;
+        draft.discard()?;
+        println!("Create new post! {}", created.url);
     } else {
-        println!("creating new post is aborted");
+        draft.discard()?;
+        println!("creating new post is canceled");
     }
     Ok(())
 }
 
+const NEW_POST_ABORTED: &str = "creating new post is aborted";
+const EDIT_POST_ABORTED: &str = "editing post is aborted";
+
+/// Returns the draft if the editor exited successfully. Otherwise the draft is kept
+/// unless it still has the text it was created with
+/// # Args
+/// - initial: Text written into the new draft. `None` for a resumed draft
+/// - aborted: Message printed when the editor failed
+fn editor_succeeded(
+    draft: Draft,
+    exit_status: ExitStatus,
+    initial: Option<&str>,
+    aborted: &str,
+) -> Result<Option<Draft>> {
+    if exit_status.success() {
+        return Ok(Some(draft));
+    }
+    println!("{}", aborted);
+    let untouched = initial.is_some_and(|initial| draft.read().is_ok_and(|text| text == initial));
+    if untouched {
+        draft.discard()?;
+    } else {
+        print_draft_kept(&draft);
+    }
+    Ok(None)
+}
+
 /// Edit post
 /// # Args
 /// - id: Post ID
-async fn edit_post(esa: &Esa, id: i32, esa_env: &Env) -> Result<()> {
+/// - save: WIP, change message and confirmation
+async fn edit_post(
+    esa: &Esa,
+    id: i32,
+    esa_env: &Env,
+    config: &Config,
+    save: &SaveOpts,
+) -> Result<()> {
+    let editor_command = EditorCommand::resolve(config)?;
     let post = esa.post(id).await?;
-    let post_content = tmp_file::format_post_content(&post.full_name, &post.body_md);
+    let base_content =
+        tmp_file::format_post_content(&tmp_file::FrontMatter::from_post(&post), &post.body_md);
+    let post_content = {
+        let mut front_matter = tmp_file::FrontMatter::from_post(&post);
+        front_matter.wip = save.wip().or(front_matter.wip);
+        front_matter.message = save.message.clone();
+        tmp_file::format_post_content(&front_matter, &post.body_md)
+    };
+
+    let drafts = Draft::list(esa_env)?
+        .into_iter()
+        .filter(|draft| draft.team == esa.team_id().to_string() && draft.number == Some(id))
+        .map(|draft| draft.name().to_string())
+        .collect::<Vec<_>>();
+    if !drafts.is_empty() {
+        eprintln!(
+            "unsaved drafts of this post exist: {}. `esa-cli draft resume <NAME>` reopens them.",
+            drafts.join(", ")
+        );
+    }
 
-    let editor = Editor::new(esa_env);
-    let exit_status = editor.open(&post_content);
+    let draft = Draft::create(esa_env, esa.team_id(), Some(id))?;
+    draft.write_base(&base_content)?;
+    let exit_status = Editor::new(&editor_command, &draft).open(&post_content)?;
+    let Some(draft) = editor_succeeded(draft, exit_status, Some(&post_content), EDIT_POST_ABORTED)?
+    else {
+        return Ok(());
+    };
+    save_edited_post(esa, config, id, &editor_command, draft, save).await
+}
 
-    if exit_status.success() {
-        if let Some(diff) = editor.diff() {
-            let post_content = tmp_file::parse_post(&diff)?;
-            let edited_post = post.edit(
-                post_content.full_name,
-                post_content.body_md,
-                Some(post_content.tags),
-                post_content.category,
-                true,
-                None,
-            );
+/// Edit only name, tags and category of posts.
+/// Continues with the rest of the posts when editing one fails
+/// # Args
+/// - ids: Post IDs
+/// - change: Returns new name, tags and category of the post
+async fn edit_metadata<F>(esa: &Esa, ids: &[i32], message: Option<String>, change: F) -> Result<()>
+where
+    F: Fn(&esa::post::Post) -> (String, Vec<String>, Option<String>),
+{
+    let mut failed = 0;
+    for &id in ids {
+        let result = async {
+            let post = esa.post(id).await?;
+            let (name, tags, category) = change(&post);
+            if name == post.name && tags == post.tags && category == post.category {
+                println!("#{} is not changed", id);
+                return Ok(());
+            }
+            let full_name = post.full_name.clone();
+            let edited_post = post.edit_metadata(name, tags, category, message.clone());
             let edited = esa.edit_post(id, &edited_post).await?;
-            println!("Edit post! {}", edited.url);
+            println!("#{}: {} -> {}", id, full_name, edited.full_name);
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+        if let Err(e) = result {
+            eprintln!("#{}: {:#}", id, e);
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        bail!("failed to edit {} of {} posts", failed, ids.len());
+    }
+    Ok(())
+}
+
+/// Result of sending an edited post
+enum EditOutcome {
+    Edited(esa::post::PostEdited),
+    /// The post was updated by someone else while editing
+    Conflicted(esa::post::Post),
+    /// The user wants to edit the draft again after seeing the diff
+    Reedit,
+    /// The user declined saving after seeing the diff
+    Declined,
+}
+
+/// Edit post with the draft closed in the editor
+async fn save_edited_post(
+    esa: &Esa,
+    config: &Config,
+    id: i32,
+    editor_command: &EditorCommand,
+    draft: Draft,
+    save: &SaveOpts,
+) -> Result<()> {
+    loop {
+        let content = match draft.diff()? {
+            Some(content) => content,
+            None => {
+                draft.discard()?;
+                println!("editing post is canceled");
+                return Ok(());
+            }
+        };
+
+        let outcome = keep_draft_on_error(
+            send_edited_post(esa, config, id, &draft, &content, save).await,
+            &draft,
+        )?;
+        let remote = match outcome {
+            EditOutcome::Edited(edited) => {
+                draft.discard()?;
+                println!("Edit post! {}", edited.url);
+                if edited.overlapped {
+                    println!(
+                        "warning: the post was also edited by someone else and esa merged the changes. check the post and `esa-cli post {} --edit` if needed.",
+                        id
+                    );
+                }
+                return Ok(());
+            }
+            EditOutcome::Conflicted(remote) => remote,
+            EditOutcome::Reedit => {
+                let exit_status = Editor::new(editor_command, &draft).reopen()?;
+                if !exit_status.success() {
+                    println!("{}", EDIT_POST_ABORTED);
+                    print_draft_kept(&draft);
+                    return Ok(());
+                }
+                continue;
+            }
+            EditOutcome::Declined => {
+                println!("editing post is canceled");
+                print_draft_kept(&draft);
+                return Ok(());
+            }
+        };
+
+        println!(
+            "post {} was updated by {} at {} while editing. (revision {})",
+            id,
+            remote.updated_by.screen_name,
+            remote.updated_at.format("%Y-%m-%d %H:%M:%S"),
+            remote.revision_number
+        );
+        if !confirm("Do you merge the changes and reopen the editor")? {
+            println!("editing post is aborted");
+            print_draft_kept(&draft);
+            return Ok(());
+        }
+
+        let base = draft.read_base()?.unwrap_or_default();
+        let theirs = tmp_file::format_post_content(
+            &tmp_file::FrontMatter::from_post(&remote),
+            &remote.body_md,
+        );
+        let merged = conflict::merge(&base, &content, &theirs);
+        if let conflict::Merged::Conflicted(_) = merged {
+            println!("conflicts are marked with <<<<<<< and >>>>>>>. resolve them in the editor.");
+        }
+        draft.write(merged.text())?;
+        draft.write_base(&theirs)?;
+
+        let exit_status = Editor::new(editor_command, &draft).reopen()?;
+        if !exit_status.success() {
+            println!("{}", EDIT_POST_ABORTED);
+            print_draft_kept(&draft);
+            return Ok(());
+        }
+    }
+}
+
+/// Sends the edited post unless it conflicts with the latest revision.
+/// Shows the diff and asks confirmation unless `--yes`
+async fn send_edited_post(
+    esa: &Esa,
+    config: &Config,
+    id: i32,
+    draft: &Draft,
+    content: &str,
+    save: &SaveOpts,
+) -> Result<EditOutcome> {
+    if let Some(line) = conflict::marker_line(content) {
+        bail!("line {}: conflict marker is left", line);
+    }
+    let mut parsed = tmp_file::parse_post(content)?;
+    save.apply(&mut parsed);
+    if !save.no_verify {
+        hooks::verify(config, &parsed, &draft.path)?;
+    }
+    let base_revision = match draft.read_base()? {
+        Some(base) => tmp_file::parse_post(&base)?.revision_number,
+        None => parsed.revision_number,
+    };
+
+    let post = esa.post(id).await?;
+    if base_revision.is_some_and(|revision| revision != post.revision_number) {
+        return Ok(EditOutcome::Conflicted(post));
+    }
+
+    if !save.yes {
+        let diff = diff::post_diff(&post, &parsed, diff::use_color());
+        if diff.is_empty() {
+            println!("no changes in title, category, tags and body");
         } else {
-            println!("creating new post is canceled");
+            print!("{}", diff);
         }
-    } else {
-        println!("creating new post is aborted");
+        match &prompt("Save the changes? [y]es / [n]o / [e]dit again")?[..] {
+            "y" | "yes" => {}
+            "e" | "edit" => return Ok(EditOutcome::Reedit),
+            _ => return Ok(EditOutcome::Declined),
+        }
+        if parsed.message.is_none() {
+            let message = prompt_raw("Change message (optional)")?;
+            if !message.is_empty() {
+                parsed.message = Some(message);
+            }
+        }
+    }
+
+    // 指定がなければ現在の WIP 状態を維持する
+    let wip = parsed.wip.unwrap_or(post.wip);
+    let post_content = parsed.content;
+    let edited_post = post.edit(
+        post_content.name,
+        post_content.body_md,
+        Some(post_content.tags),
+        post_content.category,
+        wip,
+        parsed.message,
+    );
+    let edited = esa.edit_post(id, &edited_post).await?;
+    Ok(EditOutcome::Edited(edited))
+}
+
+/// Tells how to resume the draft if saving failed
+fn keep_draft_on_error<T>(result: Result<T>, draft: &Draft) -> Result<T> {
+    if result.is_err() {
+        print_draft_kept(draft);
+    }
+    result
+}
+
+fn print_draft_kept(draft: &Draft) {
+    eprintln!(
+        "the draft is kept in {}. `esa-cli draft resume {}` reopens it.",
+        draft.path.display(),
+        draft.name()
+    );
+}
+
+/// Print drafts
+fn print_drafts(esa_env: &Env) -> Result<()> {
+    for draft in Draft::list(esa_env)? {
+        println!(
+            "{}\t{}\t{}\t{}",
+            draft.name(),
+            draft.team,
+            draft
+                .number
+                .map(|n| format!("post {}", n))
+                .unwrap_or_else(|| String::from("new post")),
+            draft
+                .modified()
+                .map(|m| m.format("%Y-%m-%d %H:%M:%S").to_string())
+                .unwrap_or_default()
+        );
     }
     Ok(())
 }
 
+/// Reopen the draft and save it
+async fn resume_draft(config: &Config, esa_env: &Env, name: &str, save: &SaveOpts) -> Result<()> {
+    let draft = Draft::find(esa_env, name)?;
+    let team = select_team(config, Some(draft.team.clone()))?;
+    let esa = Esa::new(team);
+
+    let editor_command = EditorCommand::resolve(config)?;
+    let exit_status = Editor::new(&editor_command, &draft).reopen()?;
+    // 再開した下書きは中断しても消さない
+    let aborted = match draft.number {
+        Some(_) => EDIT_POST_ABORTED,
+        None => NEW_POST_ABORTED,
+    };
+    let Some(draft) = editor_succeeded(draft, exit_status, None, aborted)? else {
+        return Ok(());
+    };
+    match draft.number {
+        Some(id) => save_edited_post(&esa, config, id, &editor_command, draft, save).await,
+        None => save_new_post(&esa, config, draft, save).await,
+    }
+}
+
+/// Discard the draft
+fn discard_draft(esa_env: &Env, name: &str) -> Result<()> {
+    let draft = Draft::find(esa_env, name)?;
+    draft.discard()?;
+    println!("{} is discarded.", name);
+    Ok(())
+}
+
 /// Delete post
 /// # Args
 /// - id: Post ID
@@ -250,14 +1496,100 @@ async fn delete_post(esa: &Esa, id: i32) -> Result<()> {
 /// - Ok(false): input others
 /// - Err: input error
 fn confirm(message: &str) -> Result<bool> {
-    print!("{} (y/N): ", message);
+    match &prompt(&format!("{} (y/N)", message))?[..] {
+        "y" | "yes" => Ok(true),
+        _ => Ok(false),
+    }
+}
+
+/// print message and read the answer
+/// # Returns
+/// - Ok(answer): trimmed and lowercased input
+/// - Err: input error
+fn prompt(message: &str) -> Result<String> {
+    Ok(prompt_raw(message)?.to_lowercase())
+}
+
+/// print message and read the answer as it is except surrounding spaces
+fn prompt_raw(message: &str) -> Result<String> {
+    print!("{}: ", message);
     io::stdout().flush().unwrap();
 
     let mut answer = String::new();
     io::stdin().read_line(&mut answer)?;
 
-    match &(answer.trim().to_lowercase())[..] {
-        "y" | "yes" => Ok(true),
-        _ => Ok(false),
+    Ok(answer.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_post_tag() {
+        let tag_args = |args: &[&str]| {
+            let args = ["esa-cli", "post", "tag"].iter().chain(args);
+            match Opts::try_parse_from(args).unwrap().sub {
+                SubCmd::Post {
+                    cmd:
+                        Some(PostCmd::Tag {
+                            args,
+                            removes,
+                            message,
+                        }),
+                    ..
+                } => (post_meta::parse_tag_args(&args, &removes).unwrap(), message),
+                sub => panic!("unexpected {:?}", sub),
+            }
+        };
+
+        let (parsed, message) = tag_args(&["12", "+foo", "-bar", "-m", "retag"]);
+        assert_eq!(vec![12], parsed.ids);
+        assert_eq!(
+            vec![
+                post_meta::TagChange::Add(String::from("foo")),
+                post_meta::TagChange::Remove(String::from("bar"))
+            ],
+            parsed.changes
+        );
+        assert_eq!(Some(String::from("retag")), parsed.message);
+        assert_eq!(None, message);
+
+        let (parsed, _) = tag_args(&["-m", "retag", "--remove", "m", "12", "--", "-bar"]);
+        assert_eq!(vec![12], parsed.ids);
+        assert_eq!(
+            vec![
+                post_meta::TagChange::Remove(String::from("m")),
+                post_meta::TagChange::Remove(String::from("bar"))
+            ],
+            parsed.changes
+        );
+        assert_eq!(Some(String::from("retag")), parsed.message);
+    }
+
+    #[test]
+    fn test_new_post_editor_content() {
+        let new_post = |args: &[&str]| {
+            let args = ["esa-cli", "post", "new"].iter().chain(args);
+            match Opts::try_parse_from(args).unwrap().sub {
+                SubCmd::Post {
+                    cmd: Some(PostCmd::New(new_post)),
+                    ..
+                } => new_post,
+                sub => panic!("unexpected {:?}", sub),
+            }
+        };
+
+        assert_eq!(
+            tmp_file::TMP_FILE_DEFAULT_VALUE,
+            new_post(&["--ship"]).editor_content()
+        );
+        let content = new_post(&["--title", "T", "--category", "C", "--tag", "x"]).editor_content();
+        let (front_matter, body) = tmp_file::split_front_matter(&content).unwrap();
+        assert_eq!(Some(String::from("T")), front_matter.title);
+        assert_eq!(Some(String::from("C")), front_matter.category);
+        assert_eq!(vec![String::from("x")], front_matter.tags);
+        assert_eq!(Some(true), front_matter.wip);
+        assert_eq!("", body);
     }
 }
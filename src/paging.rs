@@ -0,0 +1,84 @@
+use anyhow::Result;
+use chrono::{prelude::Local, DateTime, Duration};
+
+use esa::{
+    post::{Post, SearchQuery},
+    Esa,
+};
+
+const POSTS_PER_PAGE: i32 = 100;
+
+/// Posts matching a search query fetched page by page
+pub struct Pages {
+    query: SearchQuery,
+    next_page: Option<i32>,
+    fetched: usize,
+    /// Prints how many posts are fetched after each page but the last
+    progress: bool,
+}
+
+impl Pages {
+    pub fn new(query: SearchQuery) -> Self {
+        Self {
+            query,
+            next_page: Some(1),
+            fetched: 0,
+            progress: false,
+        }
+    }
+
+    pub fn with_progress(self) -> Self {
+        Self {
+            progress: true,
+            ..self
+        }
+    }
+
+    /// Posts of the next page. `None` after the last page
+    pub async fn next(&mut self, esa: &Esa) -> Result<Option<Vec<Post>>> {
+        let page = match self.next_page {
+            Some(page) => page,
+            None => return Ok(None),
+        };
+        let result = esa
+            .posts(self.query.clone().paginate(page, POSTS_PER_PAGE))
+            .await?;
+        self.fetched += result.posts.len();
+        self.next_page = result.next_page;
+        if self.progress && self.next_page.is_some() {
+            println!("fetched {} / {} posts", self.fetched, result.total_count);
+        }
+        Ok(Some(result.posts))
+    }
+}
+
+/// Fetches all pages of the posts matching the query
+pub async fn fetch_all(esa: &Esa, query: SearchQuery) -> Result<Vec<Post>> {
+    let mut pages = Pages::new(query);
+    let mut posts = Vec::new();
+    while let Some(page) = pages.next(esa).await? {
+        posts.extend(page);
+    }
+    Ok(posts)
+}
+
+/// Search term of posts updated since the time
+pub fn updated_since(since: DateTime<Local>) -> String {
+    // 更新日は日付単位でしか指定できないので 1 日さかのぼる
+    format!(
+        "updated:>{}",
+        (since - Duration::days(1)).format("%Y-%m-%d")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_updated_since() {
+        let since = Local.ymd(2021, 4, 1).and_hms(0, 30, 0);
+        assert_eq!("updated:>2021-03-31", updated_since(since));
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{bail, Context, Result};
+
+/// `+tag` or `-tag` given to `post tag`
+#[derive(Debug, PartialEq)]
+pub enum TagChange {
+    Add(String),
+    Remove(String),
+}
+
+impl TagChange {
+    pub fn parse(source: &str) -> Result<Self> {
+        if let Some(tag) = source.strip_prefix('+') {
+            Ok(TagChange::Add(Self::tag(tag, source)?))
+        } else if let Some(tag) = source.strip_prefix('-') {
+            Ok(TagChange::Remove(Self::tag(tag, source)?))
+        } else {
+            bail!("expected +TAG or -TAG but got '{}'", source)
+        }
+    }
+
+    fn tag(tag: &str, source: &str) -> Result<String> {
+        let tag = tag.trim().trim_start_matches('#');
+        if tag.is_empty() {
+            bail!("tag is empty in '{}'", source);
+        }
+        Ok(tag.to_string())
+    }
+}
+
+/// Arguments of `post tag`
+#[derive(Debug, PartialEq)]
+pub struct TagArgs {
+    pub ids: Vec<i32>,
+    pub changes: Vec<TagChange>,
+    /// `-m` or `--message` given after the tags
+    pub message: Option<String>,
+}
+
+/// Splits the arguments of `post tag` into post IDs, tag changes and the change message.
+/// clap passes the options mixed with `-TAG` as tags, so `-m` and `--remove` are taken here
+/// # Args
+/// - args: Post IDs, `+tag` and `-tag`
+/// - removes: Values of `--remove` given before the tags
+pub fn parse_tag_args(args: &[String], removes: &[String]) -> Result<TagArgs> {
+    let mut ids = Vec::new();
+    let mut changes = Vec::new();
+    let mut message = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--" => {}
+            "-m" | "--message" => {
+                message = Some(args.next().context("--message needs a value")?.clone());
+            }
+            "-r" | "--remove" => {
+                let tag = args.next().context("--remove needs a value")?;
+                changes.push(TagChange::Remove(TagChange::tag(tag, tag)?));
+            }
+            arg if arg.starts_with("--") => bail!(
+                "unexpected option '{}'. only -m, --message, -r and --remove are taken after the tags",
+                arg
+            ),
+            arg => match arg.parse::<i32>() {
+                Ok(id) if !arg.starts_with(['+', '-']) => ids.push(id),
+                _ => changes.push(TagChange::parse(arg)?),
+            },
+        }
+    }
+    for tag in removes {
+        changes.push(TagChange::Remove(TagChange::tag(tag, tag)?));
+    }
+    if ids.is_empty() {
+        bail!("post ID is required");
+    }
+    if changes.is_empty() {
+        bail!("+TAG or -TAG is required");
+    }
+    Ok(TagArgs {
+        ids,
+        changes,
+        message,
+    })
+}
+
+/// Tags after the changes. The order of the existing tags is kept
+pub fn apply_tag_changes(tags: &[String], changes: &[TagChange]) -> Vec<String> {
+    let mut tags = tags.to_vec();
+    for change in changes {
+        match change {
+            TagChange::Add(tag) => {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+            TagChange::Remove(tag) => tags.retain(|t| t != tag),
+        }
+    }
+    tags
+}
+
+/// Category given to `post mv`. `/` or empty moves the post to the top
+pub fn normalize_category(category: &str) -> Option<String> {
+    let category = category.trim().trim_matches('/');
+    if category.is_empty() {
+        None
+    } else {
+        Some(category.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag_args() {
+        let strings = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            TagArgs {
+                ids: vec![12, 34],
+                changes: vec![
+                    TagChange::Add(String::from("foo")),
+                    TagChange::Remove(String::from("bar")),
+                    TagChange::Remove(String::from("baz")),
+                ],
+                message: Some(String::from("retag")),
+            },
+            parse_tag_args(
+                &strings(&["12", "34", "+foo", "-#bar", "--message", "retag"]),
+                &strings(&["baz"])
+            )
+            .unwrap()
+        );
+        assert!(parse_tag_args(&strings(&["12", "-bar", "-m"]), &[]).is_err());
+        assert!(parse_tag_args(&strings(&["12", "-bar", "--ship"]), &[]).is_err());
+        assert!(parse_tag_args(&strings(&["12", "foo"]), &[]).is_err());
+        assert!(parse_tag_args(&strings(&["+foo"]), &[]).is_err());
+        assert!(parse_tag_args(&strings(&["12"]), &strings(&[" "])).is_err());
+    }
+
+    #[test]
+    fn test_apply_tag_changes() {
+        let tags = vec![String::from("日報"), String::from("bar")];
+        assert_eq!(
+            vec![String::from("日報"), String::from("foo")],
+            apply_tag_changes(
+                &tags,
+                &[
+                    TagChange::Add(String::from("foo")),
+                    TagChange::Remove(String::from("bar")),
+                    TagChange::Add(String::from("日報")),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn test_normalize_category() {
+        assert_eq!(None, normalize_category("/"));
+        assert_eq!(
+            Some(String::from("dev/notes")),
+            normalize_category("/dev/notes/")
+        );
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::{prelude::Local, DateTime, Datelike};
+
+use esa::post::Post;
+
+use super::tmp_file::FrontMatter;
+
+/// Category where esa keeps template posts
+pub const TEMPLATES_CATEGORY: &str = "Templates";
+
+/// Values of the placeholders in template posts
+pub struct Variables<'a> {
+    pub now: DateTime<Local>,
+    /// screen_name of the user
+    pub me: &'a str,
+}
+
+/// Expands `%{Year}`, `%{year}`, `%{month}`, `%{day}`, `%{week}` and `%{me}`.
+/// Unknown placeholders are left as they are
+pub fn expand(text: &str, variables: &Variables) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("%{") {
+        expanded.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let end = match placeholder.find('}') {
+            Some(end) => end,
+            None => {
+                rest = placeholder;
+                break;
+            }
+        };
+        match value(&placeholder[2..end], variables) {
+            Some(value) => expanded.push_str(&value),
+            None => expanded.push_str(&placeholder[..=end]),
+        }
+        rest = &placeholder[end + 1..];
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+fn value(name: &str, variables: &Variables) -> Option<String> {
+    let now = variables.now;
+    let value = match name {
+        "Year" => now.format("%Y").to_string(),
+        "year" => now.format("%y").to_string(),
+        "month" => now.format("%m").to_string(),
+        "day" => now.format("%d").to_string(),
+        "week" => {
+            const WEEKDAYS: [&str; 7] = ["日", "月", "火", "水", "木", "金", "土"];
+            WEEKDAYS[now.weekday().num_days_from_sunday() as usize].to_string()
+        }
+        "me" => variables.me.to_string(),
+        _ => return None,
+    };
+    Some(value)
+}
+
+/// Front matter and body of a new post from the template.
+/// A name like `日報/%{Year}/%{month}/%{day}` gives the category,
+/// otherwise the category of the template without `Templates/` is used
+pub fn new_post(template: &Post, variables: &Variables) -> (FrontMatter, String) {
+    let name = expand(&template.name, variables);
+    let (category, name) = match name.rsplit_once('/') {
+        Some((category, name)) => (Some(category.to_string()), name.to_string()),
+        None => (
+            template
+                .category
+                .as_deref()
+                .and_then(strip_templates_category)
+                .map(|category| expand(&category, variables)),
+            name,
+        ),
+    };
+    let front_matter = FrontMatter {
+        title: Some(name),
+        category,
+        tags: template.tags.clone(),
+        wip: Some(true),
+        ..FrontMatter::default()
+    };
+    (front_matter, expand(&template.body_md, variables))
+}
+
+fn strip_templates_category(category: &str) -> Option<String> {
+    if category == TEMPLATES_CATEGORY {
+        return None;
+    }
+    match category.strip_prefix(&format!("{}/", TEMPLATES_CATEGORY)) {
+        Some(rest) => Some(rest.to_string()),
+        None => Some(category.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_expand() {
+        let variables = Variables {
+            now: Local.ymd(2021, 3, 7).and_hms(9, 0, 0),
+            me: "koizr",
+        };
+        assert_eq!(
+            "日報/2021/03/07 (日) koizr 21",
+            expand(
+                "日報/%{Year}/%{month}/%{day} (%{week}) %{me} %{year}",
+                &variables
+            )
+        );
+        assert_eq!("100%{unknown} %{", expand("100%{unknown} %{", &variables));
+    }
+
+    #[test]
+    fn test_strip_templates_category() {
+        assert_eq!(None, strip_templates_category("Templates"));
+        assert_eq!(
+            Some(String::from("日報")),
+            strip_templates_category("Templates/日報")
+        );
+        assert_eq!(
+            Some(String::from("TemplatesX")),
+            strip_templates_category("TemplatesX")
+        );
+    }
+}
@@ -0,0 +1,49 @@
+use std::future::Future;
+
+use anyhow::Result;
+use chrono::prelude::Local;
+
+use esa::Esa;
+
+/// Requests sent for one write like reading and editing a post.
+/// Writing waits for the rate limit reset below this
+const REQUESTS_PER_WRITE: i32 = 2;
+
+pub fn is_rate_limited(error: &anyhow::Error) -> bool {
+    error
+        .downcast_ref::<esa::error::Error>()
+        .is_some_and(|e| e.is_rate_limited())
+}
+
+/// Sleeps until the rate limit is reset if fewer than `requests` remain
+pub async fn wait(esa: &Esa, requests: i32) {
+    let rate_limit = match esa.rate_limit() {
+        Some(rate_limit) if rate_limit.remaining < requests => rate_limit,
+        _ => return,
+    };
+    if let Ok(wait) = (rate_limit.reset - Local::now()).to_std() {
+        println!(
+            "rate limit reached. waiting until {}",
+            rate_limit.reset.format("%H:%M:%S")
+        );
+        tokio::time::sleep(wait).await;
+    }
+}
+
+/// Sends the requests after waiting for the rate limit.
+/// Retries once when the rate limit is reached anyway
+pub async fn write<T, E, F, Fut>(esa: &Esa, mut request: F) -> Result<T>
+where
+    E: Into<anyhow::Error>,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+{
+    let mut retried = false;
+    loop {
+        wait(esa, REQUESTS_PER_WRITE).await;
+        match request().await.map_err(Into::into) {
+            Err(e) if !retried && is_rate_limited(&e) => retried = true,
+            result => return result,
+        }
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+
+use esa::{
+    post::{Comment, Post, PostContent, SearchQuery},
+    Esa,
+};
+
+use super::backup::Backup;
+use super::config::Env;
+use super::rate_limit;
+
+/// Progress of restoring a backup into a team. Kept to resume after interruption
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RestoreState {
+    posts: Vec<Restored>,
+    /// Number in the backup of the post being created.
+    /// The post may have been created if the restore was interrupted
+    #[serde(default)]
+    pending: Option<i32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Restored {
+    /// Number in the backup
+    source_number: i32,
+    /// Number in the team restored into
+    number: i32,
+    links_updated: bool,
+    /// Number of comments re-created
+    comments_restored: usize,
+}
+
+impl RestoreState {
+    fn path(esa_env: &Env, source: &str, target: &str) -> PathBuf {
+        esa_env
+            .dir_path
+            .join("restore")
+            .join(format!("{}_to_{}.json", source, target))
+    }
+
+    fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let state = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&state).with_context(|| format!("invalid {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    fn numbers(&self) -> HashMap<i32, i32> {
+        self.posts
+            .iter()
+            .map(|restored| (restored.source_number, restored.number))
+            .collect()
+    }
+}
+
+/// Restore posts in the backup into the team.
+/// Running again resumes from the posts not restored yet
+/// # Args
+/// - target: Directory or `.tar.zst` file made by backup
+/// - comments: Re-creates comments as quoted text
+pub async fn restore(esa: &Esa, esa_env: &Env, target: &Path, comments: bool) -> Result<()> {
+    let backup = Backup::open(target)?;
+    let manifest = match backup.manifest()? {
+        Some(manifest) => manifest,
+        None => bail!("{} is not a backup", target.display()),
+    };
+    let source = manifest.team.clone();
+    let team = esa.team_id().to_string();
+    if source == team {
+        bail!(
+            "{} is a backup of '{}'. restoring it into the same team duplicates all posts",
+            target.display(),
+            team
+        );
+    }
+    let state_path = RestoreState::path(esa_env, &source, &team);
+    let mut state = RestoreState::load(&state_path)?;
+
+    // 記事を作ってから、全記事の番号が決まった後でリンクを書き換える
+    // esa で削除された記事は戻さない
+    for entry in manifest.posts.iter().filter(|e| e.deleted_at.is_none()) {
+        if state.posts.iter().any(|r| r.source_number == entry.number) {
+            continue;
+        }
+        let post = backup.read_post(entry.number)?;
+        let content = PostContent {
+            name: post.name.clone(),
+            full_name: post.full_name.clone(),
+            body_md: Some(post.body_md.clone()),
+            tags: post.tags.clone(),
+            category: post.category.clone(),
+        };
+        let message = format!("restored from {}#{}", source, post.number);
+        let found = if state.pending == Some(post.number) {
+            find_created(esa, &content, &message).await?
+        } else {
+            None
+        };
+        // 作成と進捗の保存の間で中断すると二重に作らないように、作成前に印を残す
+        let (number, full_name) = match found {
+            Some(found) => (found.number, found.full_name),
+            None => {
+                state.pending = Some(post.number);
+                state.save(&state_path)?;
+                let created = rate_limit::write(esa, || {
+                    esa.create_post(content.clone(), post.wip, Some(message.clone()))
+                })
+                .await?;
+                (created.number, created.full_name)
+            }
+        };
+        println!("#{} -> #{} {}", post.number, number, full_name);
+        state.posts.push(Restored {
+            source_number: post.number,
+            number,
+            links_updated: false,
+            comments_restored: 0,
+        });
+        state.pending = None;
+        state.save(&state_path)?;
+    }
+
+    let numbers = state.numbers();
+    for i in 0..state.posts.len() {
+        let (source_number, number) = (state.posts[i].source_number, state.posts[i].number);
+        let source_post = backup.read_post(source_number)?;
+
+        if !state.posts[i].links_updated {
+            let body = remap_links(&source_post.body_md, &source, &team, &numbers);
+            if body != source_post.body_md {
+                rate_limit::write(esa, || {
+                    update_links(esa, number, body.clone(), "update links to restored posts")
+                })
+                .await?;
+            }
+            state.posts[i].links_updated = true;
+            state.save(&state_path)?;
+        }
+
+        if comments {
+            let restored = state.posts[i].comments_restored;
+            for comment in source_post.comments.iter().flatten().skip(restored) {
+                let body = remap_links(&quote_comment(comment), &source, &team, &numbers);
+                rate_limit::write(esa, || esa.create_comment(number, body.clone())).await?;
+                state.posts[i].comments_restored += 1;
+                state.save(&state_path)?;
+            }
+        }
+    }
+
+    println!(
+        "restored {} posts from {} into {}. progress is kept in {}",
+        state.posts.len(),
+        target.display(),
+        team,
+        state_path.display()
+    );
+    Ok(())
+}
+
+/// Replaces the body of the post with the links rewritten. The other fields are kept
+pub async fn update_links(esa: &Esa, number: i32, body: String, message: &str) -> Result<()> {
+    let post = esa.post(number).await?;
+    if body == post.body_md {
+        return Ok(());
+    }
+    let (name, tags, category, wip) = (
+        post.name.clone(),
+        post.tags.clone(),
+        post.category.clone(),
+        post.wip,
+    );
+    let message = Some(message.to_string());
+    let edited_post = post.edit(name, Some(body), Some(tags), category, wip, message);
+    esa.edit_post(number, &edited_post).await?;
+    println!("#{}: updated links", number);
+    Ok(())
+}
+
+/// Post created before the restore or the import was interrupted, found by the title and the message
+pub async fn find_created(esa: &Esa, content: &PostContent, message: &str) -> Result<Option<Post>> {
+    let mut query = format!("title:\"{}\"", content.name);
+    if let Some(ref category) = content.category {
+        query.push_str(&format!(" in:\"{}\"", category));
+    }
+    let search_query = SearchQuery::new(Some(query), None, None);
+    Ok(esa
+        .posts(search_query)
+        .await?
+        .posts
+        .into_iter()
+        .find(|post| {
+            post.name == content.name
+                && post.category == content.category
+                && post.message == message
+        }))
+}
+
+/// Rewrites links to posts of `source` team into links to the restored posts
+/// # Args
+/// - numbers: Post numbers in the backup and in the restored team
+pub fn remap_links(text: &str, source: &str, target: &str, numbers: &HashMap<i32, i32>) -> String {
+    let pattern = Regex::new(&format!(
+        r#"(https://{}\.esa\.io|\]\(|href=")/posts/(\d+)"#,
+        regex::escape(source)
+    ))
+    .expect("invalid link pattern");
+    let absolute = format!("https://{}.esa.io", source);
+    pattern
+        .replace_all(text, |caps: &Captures| {
+            let number = caps[2].parse::<i32>().ok();
+            match number.and_then(|number| numbers.get(&number)) {
+                Some(number) if caps[1] == absolute => {
+                    format!("https://{}.esa.io/posts/{}", target, number)
+                }
+                Some(number) => format!("{}/posts/{}", &caps[1], number),
+                None => caps[0].to_string(),
+            }
+        })
+        .to_string()
+}
+
+/// Comment body quoted with the original author and date
+fn quote_comment(comment: &Comment) -> String {
+    // @ を付けると復元先のチームでメンションになるので付けない
+    let mut body = format!(
+        "comment by {} at {}:\n\n",
+        comment.created_by.screen_name,
+        comment.created_at.format("%Y-%m-%d %H:%M")
+    );
+    for line in comment.body_md.lines() {
+        body.push_str("> ");
+        body.push_str(line);
+        body.push('\n');
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remap_links() {
+        let numbers = [(12, 3), (34, 5)].into_iter().collect::<HashMap<_, _>>();
+        assert_eq!(
+            "[手順](/posts/3) https://new.esa.io/posts/5 <a href=\"/posts/5\"> /posts/99 https://other.esa.io/posts/12",
+            remap_links(
+                "[手順](/posts/12) https://old.esa.io/posts/34 <a href=\"/posts/34\"> /posts/99 https://other.esa.io/posts/12",
+                "old",
+                "new",
+                &numbers
+            )
+        );
+    }
+}
@@ -0,0 +1,560 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use esa::{post::Post, Esa};
+
+use super::assets::{self, Assets};
+use super::cache;
+use super::config::{Config, Env};
+use super::hooks;
+use super::tmp_file;
+use super::workspace::{self, Entry, Workspace};
+
+/// Pull posts into the directory.
+/// Files changed locally are not overwritten
+/// # Args
+/// - dir: Directory mirroring the posts
+/// - query: Search query of the posts. `None` pulls all posts
+/// - attachments: Downloads attached files into `assets/`.
+///   Always done once the directory has them
+pub async fn pull(
+    esa: &Esa,
+    esa_env: &Env,
+    dir: &Path,
+    query: Option<String>,
+    attachments: bool,
+) -> Result<()> {
+    let mut workspace = Workspace::open_or_create(dir, esa.team_id(), query)?;
+    let posts = workspace::fetch_posts(esa, &workspace).await?;
+    cache::remember(esa_env, &esa.team_id().to_string(), &posts);
+    let mut assets = Assets::open(dir)?;
+    if attachments || Assets::exists(dir) {
+        for post in &posts {
+            assets.fetch(esa, &post.body_md).await?;
+        }
+    }
+
+    // 途中で失敗しても書き込んだファイルは manifest に残す
+    let result = write_posts(&mut workspace, &posts, &assets);
+    workspace.save()?;
+    let (added, updated, skipped) = result?;
+
+    println!(
+        "pulled {} posts into {}: {} added, {} updated, {} skipped",
+        posts.len(),
+        dir.display(),
+        added,
+        updated,
+        skipped
+    );
+    Ok(())
+}
+
+/// Writes the posts changed remotely.
+/// Returns the number of added, updated and skipped posts
+fn write_posts(
+    workspace: &mut Workspace,
+    posts: &[Post],
+    assets: &Assets,
+) -> Result<(usize, usize, usize)> {
+    let (mut added, mut updated, mut skipped) = (0, 0, 0);
+    for post in posts {
+        let entry = workspace.entry(post.number).cloned();
+        if let Some(ref entry) = entry {
+            if entry.revision_number == post.revision_number {
+                continue;
+            }
+            if workspace.is_modified(entry)? {
+                println!("skipped {} (changed locally)", entry.path);
+                skipped += 1;
+                continue;
+            }
+        }
+
+        let path = workspace.path_for(post);
+        let content = assets.relink(&workspace::format_post(post), &assets::root_of(&path));
+        workspace.write(&path, &content)?;
+        match entry {
+            Some(entry) => {
+                if entry.path != path {
+                    fs::remove_file(workspace.full_path(&entry.path))?;
+                    println!("R {} -> {}", entry.path, path);
+                } else {
+                    println!("U {}", path);
+                }
+                updated += 1;
+            }
+            None => {
+                println!("A {}", path);
+                added += 1;
+            }
+        }
+        workspace.set_entry(workspace::entry_of(post, path, &content));
+    }
+    Ok((added, updated, skipped))
+}
+
+/// Options of push
+pub struct PushOptions {
+    /// Only shows what would be pushed
+    pub dry_run: bool,
+    /// Change message for all posts
+    pub message: Option<String>,
+    /// Runs the built-in checks and the hooks
+    pub verify: bool,
+}
+
+/// Result of pushing a file
+pub enum Pushed {
+    Created,
+    Updated,
+    Unchanged,
+    Conflicted,
+    Skipped,
+}
+
+/// Push files changed locally to esa.
+/// Posts updated on esa since the last pull are not overwritten
+/// # Args
+/// - paths: Files or directories in a pulled directory. Empty pushes the current directory
+pub async fn push(
+    esa: &Esa,
+    config: &Config,
+    paths: &[PathBuf],
+    options: &PushOptions,
+) -> Result<()> {
+    let start = paths.first().cloned().unwrap_or_else(|| PathBuf::from("."));
+    let mut workspace = Workspace::find(&start)?;
+    check_team(esa, &workspace)?;
+    let targets = paths
+        .iter()
+        .map(|path| workspace.relative(path))
+        .collect::<Result<Vec<_>>>()?;
+    let in_targets = |path: &str| {
+        targets.is_empty()
+            || targets
+                .iter()
+                .any(|t| t.is_empty() || path == t || path.starts_with(&format!("{}/", t)))
+    };
+
+    let (mut created, mut updated, mut conflicted, mut failed) = (0, 0, 0, 0);
+    let mut numbers = HashSet::new();
+    for path in workspace.files()?.iter().filter(|path| in_targets(path)) {
+        match push_file(esa, config, &mut workspace, path, options, &mut numbers).await {
+            Ok(Pushed::Created) => created += 1,
+            Ok(Pushed::Updated) => updated += 1,
+            Ok(Pushed::Conflicted) => conflicted += 1,
+            Ok(Pushed::Unchanged) | Ok(Pushed::Skipped) => {}
+            Err(e) => {
+                eprintln!("failed to push {}: {:#}", path, e);
+                failed += 1;
+            }
+        }
+    }
+    for entry in &workspace.manifest.posts {
+        if in_targets(&entry.path)
+            && !numbers.contains(&entry.number)
+            && !workspace.full_path(&entry.path).exists()
+        {
+            println!("D {} (deleted locally. not deleted on esa)", entry.path);
+        }
+    }
+    if !options.dry_run {
+        workspace.save()?;
+    }
+
+    println!(
+        "{}{} created, {} updated, {} conflicted, {} failed",
+        if options.dry_run { "dry run: " } else { "" },
+        created,
+        updated,
+        conflicted,
+        failed
+    );
+    if conflicted + failed > 0 {
+        bail!("some files are not pushed");
+    }
+    Ok(())
+}
+
+pub async fn push_file(
+    esa: &Esa,
+    config: &Config,
+    workspace: &mut Workspace,
+    path: &str,
+    options: &PushOptions,
+    numbers: &mut HashSet<i32>,
+) -> Result<Pushed> {
+    let content = workspace
+        .read(path)?
+        .with_context(|| format!("{} is not found", path))?;
+    let (front_matter, body) = tmp_file::split_front_matter(&content)?;
+    let mut assets = Assets::open(&workspace.root)?;
+    let root = assets::root_of(path);
+    let entry = match front_matter.number {
+        Some(number) => {
+            numbers.insert(number);
+            match workspace.entry(number) {
+                Some(entry) => Some(entry.clone()),
+                None => {
+                    println!("? {} (#{} is not pulled into this directory)", path, number);
+                    return Ok(Pushed::Skipped);
+                }
+            }
+        }
+        None => None,
+    };
+    if let Some(ref entry) = entry {
+        if workspace::hash(&content) == entry.hash {
+            // 移動しただけのファイルは manifest のパスだけ更新する
+            if entry.path != path && !options.dry_run {
+                let mut entry = entry.clone();
+                entry.path = path.to_string();
+                workspace.set_entry(entry);
+            }
+            return Ok(Pushed::Unchanged);
+        }
+    }
+
+    let mut parsed = tmp_file::post_from_front_matter(front_matter, assets.unlink(&body, &root))
+        .map_err(|e| anyhow!(e.message))?;
+    if let Some(ref message) = options.message {
+        parsed.message = Some(message.clone());
+    }
+    if options.verify {
+        hooks::verify(config, &parsed, &workspace.full_path(path))?;
+    }
+
+    let (number, pushed) = match entry {
+        None => {
+            if options.dry_run {
+                println!("A {}", path);
+                return Ok(Pushed::Created);
+            }
+            let created = esa
+                .create_post(parsed.content, parsed.wip.unwrap_or(true), parsed.message)
+                .await?;
+            println!("A {} -> {}", path, created.url);
+            (created.number, Pushed::Created)
+        }
+        Some(entry) => {
+            let post = esa.post(entry.number).await?;
+            if post.revision_number != entry.revision_number {
+                println!(
+                    "C {} (revision {} on esa is newer than pulled revision {}. merge it by hand)",
+                    path, post.revision_number, entry.revision_number
+                );
+                return Ok(Pushed::Conflicted);
+            }
+            if options.dry_run {
+                println!("M {}", path);
+                return Ok(Pushed::Updated);
+            }
+            let content = parsed.content;
+            let wip = parsed.wip.unwrap_or(post.wip);
+            let edited_post = post.edit(
+                content.name,
+                content.body_md,
+                Some(content.tags),
+                content.category,
+                wip,
+                parsed.message,
+            );
+            let edited = esa.edit_post(entry.number, &edited_post).await?;
+            println!("M {} -> {}", path, edited.url);
+            (entry.number, Pushed::Updated)
+        }
+    };
+
+    // revision などを反映するため取得し直して書き込む
+    let post = esa.post(number).await?;
+    if Assets::exists(&workspace.root) {
+        assets.fetch(esa, &post.body_md).await?;
+    }
+    let content = assets.relink(&workspace::format_post(&post), &root);
+    workspace.write(path, &content)?;
+    workspace.set_entry(workspace::entry_of(&post, path.to_string(), &content));
+    Ok(pushed)
+}
+
+pub fn check_team(esa: &Esa, workspace: &Workspace) -> Result<()> {
+    if workspace.manifest.team != esa.team_id().to_string() {
+        bail!(
+            "{} is pulled from team '{}'. use --team {}",
+            workspace.root.display(),
+            workspace.manifest.team,
+            workspace.manifest.team
+        );
+    }
+    Ok(())
+}
+
+/// File in the pulled directory
+#[derive(Debug, PartialEq)]
+pub struct LocalFile {
+    pub path: String,
+    /// `number` in the front matter
+    pub number: Option<i32>,
+    pub hash: String,
+}
+
+/// Post on esa
+#[derive(Debug, PartialEq)]
+pub struct RemotePost {
+    pub number: i32,
+    pub revision_number: i32,
+    pub full_name: String,
+}
+
+/// Difference between the directory, the manifest and esa
+#[derive(Debug, PartialEq)]
+pub enum Change {
+    /// File without number to be created by push
+    Added(String),
+    Modified(String),
+    /// Pulled file removed locally
+    Deleted(String),
+    /// File with number not in the manifest
+    Untracked(String),
+    /// Updated on esa since pull. path, pulled revision and revision on esa
+    RemoteUpdated(String, i32, i32),
+    /// Pulled post not found on esa
+    RemoteDeleted(String),
+    /// Post on esa not pulled yet
+    RemoteAdded(i32, String),
+}
+
+/// Compares local files and posts on esa with the manifest
+pub fn changes(locals: &[LocalFile], manifest: &[Entry], remotes: &[RemotePost]) -> Vec<Change> {
+    let mut changes = Vec::new();
+    for local in locals {
+        let entry = local
+            .number
+            .and_then(|number| manifest.iter().find(|entry| entry.number == number));
+        match (local.number, entry) {
+            (None, _) => changes.push(Change::Added(local.path.clone())),
+            (Some(_), None) => changes.push(Change::Untracked(local.path.clone())),
+            (Some(_), Some(entry)) if entry.hash != local.hash => {
+                changes.push(Change::Modified(local.path.clone()))
+            }
+            _ => {}
+        }
+    }
+
+    let local_numbers = locals
+        .iter()
+        .filter_map(|local| local.number)
+        .collect::<HashSet<_>>();
+    for entry in manifest {
+        if !local_numbers.contains(&entry.number) {
+            changes.push(Change::Deleted(entry.path.clone()));
+        }
+        match remotes.iter().find(|remote| remote.number == entry.number) {
+            Some(remote) if remote.revision_number != entry.revision_number => {
+                changes.push(Change::RemoteUpdated(
+                    entry.path.clone(),
+                    entry.revision_number,
+                    remote.revision_number,
+                ))
+            }
+            Some(_) => {}
+            None => changes.push(Change::RemoteDeleted(entry.path.clone())),
+        }
+    }
+
+    for remote in remotes {
+        if !manifest.iter().any(|entry| entry.number == remote.number) {
+            changes.push(Change::RemoteAdded(remote.number, remote.full_name.clone()));
+        }
+    }
+    changes
+}
+
+/// Print changes in the pulled directory and on esa
+/// # Args
+/// - dir: Pulled directory. Defaults to the current directory
+pub async fn status(esa: &Esa, dir: Option<PathBuf>) -> Result<()> {
+    let workspace = Workspace::find(&dir.unwrap_or_else(|| PathBuf::from(".")))?;
+    check_team(esa, &workspace)?;
+    let locals = local_files(&workspace)?;
+    let remotes = workspace::fetch_posts(esa, &workspace)
+        .await?
+        .into_iter()
+        .map(|post| RemotePost {
+            number: post.number,
+            revision_number: post.revision_number,
+            full_name: post.full_name,
+        })
+        .collect::<Vec<_>>();
+    let changes = changes(&locals, &workspace.manifest.posts, &remotes);
+    if changes.is_empty() {
+        println!("nothing changed in {}", workspace.root.display());
+        return Ok(());
+    }
+
+    let local = changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::Added(path) => Some(format!("new:       {}", path)),
+            Change::Modified(path) => Some(format!("modified:  {}", path)),
+            Change::Deleted(path) => Some(format!("deleted:   {}", path)),
+            Change::Untracked(path) => Some(format!("untracked: {}", path)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let remote = changes
+        .iter()
+        .filter_map(|change| match change {
+            Change::RemoteUpdated(path, pulled, latest) => Some(format!(
+                "updated:   {} (revision {} -> {})",
+                path, pulled, latest
+            )),
+            Change::RemoteDeleted(path) => Some(format!("deleted:   {}", path)),
+            Change::RemoteAdded(number, full_name) => {
+                Some(format!("new:       #{} {}", number, full_name))
+            }
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !local.is_empty() {
+        println!("Changes not pushed:");
+        for line in local {
+            println!("    {}", line);
+        }
+    }
+    if !remote.is_empty() {
+        println!("Changes on esa since pull:");
+        for line in remote {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Print diffs of local files against the posts on esa
+/// # Args
+/// - paths: Files or directories. Empty shows files changed in the current directory
+pub async fn diff(esa: &Esa, paths: &[PathBuf]) -> Result<()> {
+    let start = paths.first().cloned().unwrap_or_else(|| PathBuf::from("."));
+    let workspace = Workspace::find(&start)?;
+    check_team(esa, &workspace)?;
+    let targets = paths
+        .iter()
+        .map(|path| workspace.relative(path))
+        .collect::<Result<Vec<_>>>()?;
+    let color = super::diff::use_color();
+    let assets = Assets::open(&workspace.root)?;
+
+    for local in local_files(&workspace)? {
+        let explicit = targets.contains(&local.path);
+        let in_targets = targets.is_empty()
+            || explicit
+            || targets
+                .iter()
+                .any(|t| t.is_empty() || local.path.starts_with(&format!("{}/", t)));
+        if !in_targets {
+            continue;
+        }
+        let entry = local.number.and_then(|number| workspace.entry(number));
+        // 指定されたファイル以外は手元で変更したものだけ比べる
+        let changed = entry.is_none_or(|entry| entry.hash != local.hash);
+        if !explicit && !changed {
+            continue;
+        }
+        let remote = match local.number {
+            Some(number) => assets.relink(
+                &workspace::format_post(&esa.post(number).await?),
+                &assets::root_of(&local.path),
+            ),
+            None => String::new(),
+        };
+        let content = workspace.read(&local.path)?.unwrap_or_default();
+        print!(
+            "{}",
+            super::diff::unified_diff(&local.path, &remote, &content, color)
+        );
+    }
+    Ok(())
+}
+
+fn local_files(workspace: &Workspace) -> Result<Vec<LocalFile>> {
+    workspace
+        .files()?
+        .into_iter()
+        .map(|path| {
+            let content = workspace.read(&path)?.unwrap_or_default();
+            let (front_matter, _) = tmp_file::split_front_matter(&content)
+                .with_context(|| format!("invalid front matter in {}", path))?;
+            Ok(LocalFile {
+                number: front_matter.number,
+                hash: workspace::hash(&content),
+                path,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::prelude::Local;
+
+    fn entry(path: &str, number: i32, revision_number: i32, hash: &str) -> Entry {
+        Entry {
+            path: path.to_string(),
+            number,
+            revision_number,
+            updated_at: Local::now(),
+            hash: hash.to_string(),
+        }
+    }
+
+    fn local(path: &str, number: Option<i32>, hash: &str) -> LocalFile {
+        LocalFile {
+            path: path.to_string(),
+            number,
+            hash: hash.to_string(),
+        }
+    }
+
+    fn remote(number: i32, revision_number: i32) -> RemotePost {
+        RemotePost {
+            number,
+            revision_number,
+            full_name: format!("dev/post{}", number),
+        }
+    }
+
+    #[test]
+    fn test_changes() {
+        let manifest = vec![
+            entry("dev/1.md", 1, 1, "a"),
+            entry("dev/2.md", 2, 1, "b"),
+            entry("dev/3.md", 3, 1, "c"),
+            entry("dev/4.md", 4, 1, "d"),
+        ];
+        let locals = vec![
+            local("dev/1.md", Some(1), "a"),
+            local("dev/2.md", Some(2), "changed"),
+            local("dev/4.md", Some(4), "d"),
+            local("dev/new.md", None, "e"),
+            local("dev/copy.md", Some(9), "f"),
+        ];
+        let remotes = vec![remote(1, 2), remote(2, 1), remote(3, 1), remote(5, 1)];
+        assert_eq!(
+            vec![
+                Change::Modified(String::from("dev/2.md")),
+                Change::Added(String::from("dev/new.md")),
+                Change::Untracked(String::from("dev/copy.md")),
+                Change::RemoteUpdated(String::from("dev/1.md"), 1, 2),
+                Change::Deleted(String::from("dev/3.md")),
+                Change::RemoteDeleted(String::from("dev/4.md")),
+                Change::RemoteAdded(5, String::from("dev/post5")),
+            ],
+            changes(&locals, &manifest, &remotes)
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use chrono::format::{Item, StrftimeItems};
+use chrono::{prelude::Local, DateTime};
+
+use anyhow::{anyhow, bail, Result};
+
+use esa::post::{Kind, Post, Writer};
+
+/// User-defined output format for posts.
+///
+/// `{field}` is replaced with the post's field, `{field.sub}` accesses nested `Writer` fields
+/// and `{created_at:%Y-%m-%d}` formats dates with strftime syntax.
+/// `{{` and `}}` are literal braces, `\t` `\n` `\\` are escape sequences.
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Field {
+        path: Vec<String>,
+        format: Option<String>,
+    },
+}
+
+enum Value<'a> {
+    Str(&'a str),
+    Int(i32),
+    Bool(bool),
+    Date(&'a DateTime<Local>),
+    List(&'a [String]),
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self> {
+        let mut segments = Vec::new();
+        let mut literal = String::new();
+        let mut chars = source.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '}' => bail!("unmatched '}}' in format. use '}}}}' to print '}}'"),
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(other) => {
+                        literal.push('\\');
+                        literal.push(other);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' => {
+                    let mut field = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => field.push(c),
+                            None => bail!("unclosed '{{' in format"),
+                        }
+                    }
+                    if !literal.is_empty() {
+                        segments.push(Segment::Literal(std::mem::take(&mut literal)));
+                    }
+                    segments.push(parse_field(&field)?);
+                }
+                _ => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(literal));
+        }
+
+        Ok(Self { segments })
+    }
+
+    pub fn render(&self, post: &Post) -> Result<String> {
+        let mut rendered = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(s) => rendered.push_str(s),
+                Segment::Field { path, format } => {
+                    let value = lookup(post, path)
+                        .ok_or_else(|| anyhow!("unknown field '{}'", path.join(".")))?;
+                    // 書式はパース時に日付のフィールドだけに許している
+                    match (value, format) {
+                        (Value::Date(date), Some(format)) => {
+                            rendered.push_str(&date.format(format).to_string())
+                        }
+                        (Value::Date(date), None) => rendered.push_str(&date.to_rfc3339()),
+                        (Value::Str(s), _) => rendered.push_str(s),
+                        (Value::Int(i), _) => rendered.push_str(&i.to_string()),
+                        (Value::Bool(b), _) => rendered.push_str(&b.to_string()),
+                        (Value::List(list), _) => rendered.push_str(&list.join(",")),
+                    }
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+fn parse_field(source: &str) -> Result<Segment> {
+    let (path, format) = match source.split_once(':') {
+        Some((path, format)) => {
+            if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+                bail!("invalid date format '{}'", format);
+            }
+            (path, Some(String::from(format)))
+        }
+        None => (source, None),
+    };
+    let path = path.trim().split('.').map(String::from).collect::<Vec<_>>();
+    if path.iter().any(|p| p.is_empty()) {
+        bail!("invalid field '{}' in format", source);
+    }
+    let date = match is_date_field(&path) {
+        Some(date) => date,
+        None => bail!("unknown field '{}' in format", path.join(".")),
+    };
+    if format.is_some() && !date {
+        bail!(
+            "format spec is only available for dates. field '{}'",
+            path.join(".")
+        );
+    }
+    Ok(Segment::Field { path, format })
+}
+
+/// Fields of a post other than the writers, and whether each is a date
+const POST_FIELDS: &[(&str, bool)] = &[
+    ("number", false),
+    ("name", false),
+    ("full_name", false),
+    ("wip", false),
+    ("body_md", false),
+    ("body_html", false),
+    ("created_at", true),
+    ("message", false),
+    ("url", false),
+    ("updated_at", true),
+    ("tags", false),
+    ("category", false),
+    ("revision_number", false),
+    ("kind", false),
+    ("comments_count", false),
+    ("tasks_count", false),
+    ("done_tasks_count", false),
+    ("stargazers_count", false),
+    ("watchers_count", false),
+    ("star", false),
+    ("watch", false),
+];
+const WRITER_FIELDS: &[&str] = &["myself", "name", "screen_name", "icon"];
+
+/// Whether the field is a date. `None` if the field is unknown
+fn is_date_field(path: &[String]) -> Option<bool> {
+    match path {
+        [writer, field] if writer == "created_by" || writer == "updated_by" => {
+            WRITER_FIELDS.contains(&field.as_str()).then_some(false)
+        }
+        [field] => POST_FIELDS
+            .iter()
+            .find(|(name, _)| name == field)
+            .map(|(_, date)| *date),
+        _ => None,
+    }
+}
+
+fn lookup<'a>(post: &'a Post, path: &[String]) -> Option<Value<'a>> {
+    let (head, rest) = path.split_first()?;
+    let value = match head.as_str() {
+        "created_by" => return lookup_writer(&post.created_by, rest),
+        "updated_by" => return lookup_writer(&post.updated_by, rest),
+        _ if !rest.is_empty() => return None,
+        "number" => Value::Int(post.number),
+        "name" => Value::Str(&post.name),
+        "full_name" => Value::Str(&post.full_name),
+        "wip" => Value::Bool(post.wip),
+        "body_md" => Value::Str(&post.body_md),
+        "body_html" => Value::Str(&post.body_html),
+        "created_at" => Value::Date(&post.created_at),
+        "message" => Value::Str(&post.message),
+        "url" => Value::Str(&post.url),
+        "updated_at" => Value::Date(&post.updated_at),
+        "tags" => Value::List(&post.tags),
+        "category" => Value::Str(post.category.as_deref().unwrap_or("")),
+        "revision_number" => Value::Int(post.revision_number),
+        "kind" => Value::Str(match post.kind {
+            Kind::Stock => "stock",
+            Kind::Flow => "flow",
+        }),
+        "comments_count" => Value::Int(post.comments_count),
+        "tasks_count" => Value::Int(post.tasks_count),
+        "done_tasks_count" => Value::Int(post.done_tasks_count),
+        "stargazers_count" => Value::Int(post.stargazers_count),
+        "watchers_count" => Value::Int(post.watchers_count),
+        "star" => Value::Bool(post.star),
+        "watch" => Value::Bool(post.watch),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn lookup_writer<'a>(writer: &'a Writer, path: &[String]) -> Option<Value<'a>> {
+    match path {
+        [field] => match field.as_str() {
+            "myself" => Some(Value::Bool(writer.myself)),
+            "name" => Some(Value::Str(&writer.name)),
+            "screen_name" => Some(Value::Str(&writer.screen_name)),
+            "icon" => Some(Value::Str(&writer.icon)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn post() -> Post {
+        serde_json::from_str(
+            r#"
+            {
+                "number": 1,
+                "name": "記事タイトル",
+                "full_name": "開発/設計/記事タイトル #tag1 #タグ2",
+                "wip": true,
+                "body_md": "記事 body",
+                "body_html": "<p>記事 body</p>",
+                "created_at": "2026-10-01T12:00:00+09:00",
+                "message": "Add post",
+                "url": "https://docs.esa.io/posts/1",
+                "updated_at": "2026-10-02T12:00:00+09:00",
+                "tags": ["tag1", "タグ2"],
+                "category": "開発/設計",
+                "revision_number": 3,
+                "created_by": {
+                    "myself": true,
+                    "name": "Test User",
+                    "screen_name": "test_user",
+                    "icon": "https://img.esa.io/icon.png"
+                },
+                "updated_by": {
+                    "myself": false,
+                    "name": "Other User",
+                    "screen_name": "other_user",
+                    "icon": "https://img.esa.io/icon.png"
+                },
+                "kind": "flow",
+                "comments_count": 1,
+                "tasks_count": 2,
+                "done_tasks_count": 1,
+                "stargazers_count": 0,
+                "watchers_count": 1,
+                "star": false,
+                "watch": true
+            }
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template =
+            Template::parse(r"{number}\t{wip}\t{category}/{name}\t{updated_at:%Y-%m-%d}").unwrap();
+        assert_eq!(
+            "1\ttrue\t開発/設計/記事タイトル\t2026-10-02",
+            template.render(&post()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_nested_field_and_escape() {
+        let template = Template::parse("{{{updated_by.screen_name}}} {tags} {kind}").unwrap();
+        assert_eq!(
+            "{other_user} tag1,タグ2 flow",
+            template.render(&post()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_invalid_template() {
+        assert!(Template::parse("{number").is_err());
+        assert!(Template::parse("number}").is_err());
+        assert!(Template::parse("{created_at:%Q}").is_err());
+        assert!(Template::parse("{created_by.}").is_err());
+    }
+
+    #[test]
+    fn test_parse_unknown_field() {
+        assert!(Template::parse("{nmber}").is_err());
+        assert!(Template::parse("{number.sub}").is_err());
+        assert!(Template::parse("{created_by.nme}").is_err());
+        assert!(Template::parse("{number:%Y}").is_err());
+        assert!(Template::parse("{created_by:%Y}").is_err());
+    }
+
+    #[test]
+    fn test_known_fields_are_rendered() {
+        let post = post();
+        let writer_fields = WRITER_FIELDS
+            .iter()
+            .flat_map(|field| ["created_by", "updated_by"].map(|w| format!("{}.{}", w, field)));
+        let fields = POST_FIELDS
+            .iter()
+            .map(|(field, _)| field.to_string())
+            .chain(writer_fields);
+        for field in fields {
+            let template = Template::parse(&format!("{{{}}}", field)).unwrap();
+            assert!(template.render(&post).is_ok(), "{}", field);
+        }
+    }
+}
@@ -1,60 +1,361 @@
+use std::env;
 use std::fs::{self, OpenOptions};
-use std::io::Write;
+use std::io;
+use std::path::PathBuf;
 use std::process::{Command, ExitStatus};
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
+use chrono::{prelude::Local, DateTime};
+use serde::{Deserialize, Serialize};
 
-use super::config::Env;
+use esa::TeamId;
 
-pub const TMP_FILE_DEFAULT_VALUE: &str = r#"<!-- ### input post name next line ### -->
+use super::config::{Config, Env};
 
-<!-- ### input body next and subsequent lines ### -->
+/// Describes the file opened in the editor. Shown in help and generated docs
+pub const EDITOR_FORMAT_HELP: &str = r#"EDITOR FILE FORMAT:
+    Creating or editing a post opens $VISUAL, $EDITOR, `editor` in the
+    config file or vi in this order with a Markdown file which starts with
+    YAML front matter. The editor may have arguments like "code --wait".
+
+        ---
+        title: post name
+        category: category1/category2
+        tags:
+        - tag1
+        - tag2
+        wip: true
+        message: change message
+        # read only
+        number: 123
+        revision_number: 4
+        url: https://example.esa.io/posts/123
+        ---
+        body in Markdown
+
+    `title` is required. `number`, `revision_number` and `url` are shown
+    for reference and changes to them are ignored.
+    Saving the template of a new post as it is cancels creating it.
+
+    The legacy format is also accepted.
+
+        <!-- ### input post name next line ### -->
+        category1/category2/post name #tag1 #tag2
+        <!-- ### input body next and subsequent lines ### -->
+        body in Markdown"#;
+
+pub const TMP_FILE_DEFAULT_VALUE: &str = r#"---
+title:
+category:
+tags: []
+wip: true
+message:
+---
 "#;
 
-pub fn format_post_content(title: &str, body: &str) -> String {
-    format!(
-        r#"<!-- ### input post name next line ### -->
-{}
-<!-- ### input body next and subsequent lines ### -->
-{}
-"#,
-        title, body
-    )
+const FRONT_MATTER_DELIMITER: &str = "---";
+const LEGACY_NAME_MARKER: &str = "<!-- ### input post name next line ### -->";
+const LEGACY_BODY_MARKER: &str = "<!-- ### input body next and subsequent lines ### -->";
+
+/// Metadata of a post written in the front matter
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FrontMatter {
+    pub title: Option<String>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    pub wip: Option<bool>,
+    pub message: Option<String>,
+    // 以下は参照用で、編集しても反映しない
+    #[serde(skip_serializing)]
+    pub number: Option<i32>,
+    #[serde(skip_serializing)]
+    pub revision_number: Option<i32>,
+    #[serde(skip_serializing)]
+    pub url: Option<String>,
+}
+
+/// Read-only part of the front matter
+#[derive(Serialize)]
+struct ReadOnlyFrontMatter<'a> {
+    number: Option<i32>,
+    revision_number: Option<i32>,
+    url: Option<&'a str>,
+}
+
+impl FrontMatter {
+    pub fn from_post(post: &esa::post::Post) -> Self {
+        Self {
+            title: Some(post.name.clone()),
+            category: post.category.clone(),
+            tags: post.tags.clone(),
+            wip: Some(post.wip),
+            message: None,
+            number: Some(post.number),
+            revision_number: Some(post.revision_number),
+            url: Some(post.url.clone()),
+        }
+    }
+}
+
+/// Formats a post as a Markdown file with front matter
+pub fn format_post_content(front_matter: &FrontMatter, body: &str) -> String {
+    let mut content = String::from(FRONT_MATTER_DELIMITER);
+    content.push('\n');
+    push_yaml(&mut content, front_matter);
+    if front_matter.number.is_some() {
+        content.push_str("# read only\n");
+        push_yaml(
+            &mut content,
+            &ReadOnlyFrontMatter {
+                number: front_matter.number,
+                revision_number: front_matter.revision_number,
+                url: front_matter.url.as_deref(),
+            },
+        );
+    }
+    content.push_str(FRONT_MATTER_DELIMITER);
+    content.push('\n');
+    content.push_str(body);
+    content
+}
+
+/// Writes the fields as YAML. Fields not set are left blank instead of `null`
+fn push_yaml<T: Serialize>(content: &mut String, fields: &T) {
+    let value = serde_yaml::to_value(fields).expect("failed to serialize front matter");
+    let mapping = value.as_mapping().expect("front matter is not a mapping");
+    for (key, value) in mapping {
+        if value.is_null() {
+            let key = serde_yaml::to_string(key).expect("failed to serialize front matter");
+            content.push_str(key.trim_end());
+            content.push_str(":\n");
+        } else {
+            let field = [(key.clone(), value.clone())]
+                .into_iter()
+                .collect::<serde_yaml::Mapping>();
+            let field = serde_yaml::to_string(&field).expect("failed to serialize front matter");
+            content.push_str(&field);
+        }
+    }
+}
+
+/// File edited in one editor session.
+/// It is kept when sending fails and can be resumed later
+#[derive(Debug)]
+pub struct Draft {
+    pub path: PathBuf,
+    pub team: String,
+    /// `None` for a new post
+    pub number: Option<i32>,
+}
+
+impl Draft {
+    /// Creates an empty draft named `<team>_<number>.md` or `<team>_new.md`.
+    /// A sequence number is appended if the name is used by another session
+    pub fn create(env: &Env, team: &TeamId, number: Option<i32>) -> Result<Self> {
+        let stem = format!(
+            "{}_{}",
+            team,
+            number
+                .map(|n| n.to_string())
+                .unwrap_or_else(|| String::from("new"))
+        );
+        for seq in 1.. {
+            let file_name = if seq == 1 {
+                format!("{}.md", stem)
+            } else {
+                format!("{}_{}.md", stem, seq)
+            };
+            let path = env.drafts_dir_path.join(file_name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => {
+                    return Ok(Self {
+                        path,
+                        team: team.to_string(),
+                        number,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("failed to create {}", path.display()))
+                }
+            }
+        }
+        unreachable!()
+    }
+
+    /// Drafts ordered by name
+    pub fn list(env: &Env) -> Result<Vec<Self>> {
+        let mut drafts = fs::read_dir(&env.drafts_dir_path)
+            .with_context(|| format!("failed to read {}", env.drafts_dir_path.display()))?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| Self::from_path(entry.path()))
+            .collect::<Vec<_>>();
+        drafts.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(drafts)
+    }
+
+    pub fn find(env: &Env, name: &str) -> Result<Self> {
+        Self::list(env)?
+            .into_iter()
+            .find(|draft| draft.name() == name)
+            .ok_or_else(|| anyhow!("draft '{}' is not found", name))
+    }
+
+    fn from_path(path: PathBuf) -> Option<Self> {
+        if path.extension()? != "md" {
+            return None;
+        }
+        let stem = path.file_stem()?.to_str()?.to_string();
+        let mut parts = stem.split('_');
+        let team = parts.next()?.to_string();
+        let number = match parts.next()? {
+            "new" => None,
+            number => Some(number.parse().ok()?),
+        };
+        Some(Self { path, team, number })
+    }
+
+    /// Name to resume or discard the draft
+    pub fn name(&self) -> &str {
+        self.path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or_default()
+    }
+
+    pub fn modified(&self) -> Option<DateTime<Local>> {
+        let modified = fs::metadata(&self.path).ok()?.modified().ok()?;
+        Some(modified.into())
+    }
+
+    pub fn read(&self) -> Result<String> {
+        fs::read_to_string(&self.path)
+            .with_context(|| format!("failed to read {}", self.path.display()))
+    }
+
+    /// Returns the content unless it is the template of a new post
+    pub fn diff(&self) -> Result<Option<String>> {
+        let value = self.read()?;
+        if &value[..] == TMP_FILE_DEFAULT_VALUE {
+            Ok(None)
+        } else {
+            Ok(Some(value))
+        }
+    }
+
+    pub fn write(&self, text: &str) -> Result<()> {
+        fs::write(&self.path, text)
+            .with_context(|| format!("failed to write {}", self.path.display()))
+    }
+
+    fn base_path(&self) -> PathBuf {
+        self.path.with_extension("base")
+    }
+
+    /// Saves the post as fetched before editing to detect and merge concurrent edits
+    pub fn write_base(&self, text: &str) -> Result<()> {
+        let path = self.base_path();
+        fs::write(&path, text).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn read_base(&self) -> Result<Option<String>> {
+        let path = self.base_path();
+        match fs::read_to_string(&path) {
+            Ok(base) => Ok(Some(base)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("failed to read {}", path.display())),
+        }
+    }
+
+    pub fn discard(self) -> Result<()> {
+        let base_path = self.base_path();
+        if base_path.exists() {
+            fs::remove_file(&base_path)
+                .with_context(|| format!("failed to remove {}", base_path.display()))?;
+        }
+        fs::remove_file(&self.path)
+            .with_context(|| format!("failed to remove {}", self.path.display()))
+    }
+}
+
+/// Command line to open the editor such as `code --wait`
+#[derive(Debug, PartialEq)]
+pub struct EditorCommand {
+    program: String,
+    args: Vec<String>,
+}
+
+impl EditorCommand {
+    /// Uses VISUAL, EDITOR, `editor` in the config file or the default editor in this order
+    pub fn resolve(config: &Config) -> Result<Self> {
+        Self::from_sources(
+            env::var("VISUAL").ok(),
+            env::var("EDITOR").ok(),
+            config.editor(),
+        )
+    }
+
+    fn from_sources(
+        visual: Option<String>,
+        editor: Option<String>,
+        config: Option<&str>,
+    ) -> Result<Self> {
+        let (source, command) = [
+            ("VISUAL", visual.as_deref()),
+            ("EDITOR", editor.as_deref()),
+            ("editor in config file", config),
+        ]
+        .into_iter()
+        .find_map(|(source, command)| {
+            command
+                .filter(|c| !c.trim().is_empty())
+                .map(|c| (source, c))
+        })
+        .unwrap_or(("default editor", DEFAULT_EDITOR));
+
+        let mut words = shell_words::split(command)
+            .with_context(|| format!("failed to parse {} '{}'", source, command))?
+            .into_iter();
+        let program = words.next().ok_or_else(|| anyhow!("{} is empty", source))?;
+        Ok(Self {
+            program,
+            args: words.collect(),
+        })
+    }
 }
 
+#[cfg(windows)]
+const DEFAULT_EDITOR: &str = "notepad";
+#[cfg(not(windows))]
+const DEFAULT_EDITOR: &str = "vi";
+
 pub struct Editor<'a> {
-    env: &'a Env,
+    command: &'a EditorCommand,
+    draft: &'a Draft,
 }
 
 impl<'a> Editor<'a> {
-    pub fn new(env: &'a Env) -> Self {
-        Self { env }
+    pub fn new(command: &'a EditorCommand, draft: &'a Draft) -> Self {
+        Self { command, draft }
     }
 
-    pub fn open(&self, default_text: &str) -> ExitStatus {
-        OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(&self.env.tmp_file_path)
-            .expect("failed to open temporarily file")
-            .write_all(default_text.as_bytes())
-            .expect("failed to write to temporarily file");
+    /// Writes `default_text` into the draft and opens it
+    pub fn open(&self, default_text: &str) -> Result<ExitStatus> {
+        self.draft.write(default_text)?;
+        self.reopen()
+    }
 
-        log::debug!(
-            "open editor {}",
-            self.env
-                .editor_path
-                .as_os_str()
-                .to_str()
-                .unwrap_or("<no editor path>")
-        );
+    /// Opens the draft as it is
+    pub fn reopen(&self) -> Result<ExitStatus> {
+        log::debug!("open editor {:?}", self.command);
 
-        let status = Command::new(&self.env.editor_path)
-            .arg(&self.env.tmp_file_path)
-            .spawn()
-            .expect("failed to spawn text editor")
-            .wait()
-            .expect("failed to open editor");
+        let status = Command::new(&self.command.program)
+            .args(&self.command.args)
+            .arg(&self.draft.path)
+            .status()
+            .with_context(|| format!("failed to open editor '{}'", self.command.program))?;
 
         log::debug!(
             "close editor with exit status {}",
@@ -64,79 +365,267 @@ impl<'a> Editor<'a> {
                 .unwrap_or_else(|| String::from("<no exit status>"))
         );
 
-        status
+        Ok(status)
     }
+}
+
+/// Error in the file written in the editor
+#[derive(Debug, PartialEq, thiserror::Error)]
+#[error("line {line}: {message}")]
+pub struct ParseError {
+    /// 1-based line number
+    pub line: usize,
+    pub message: String,
+}
 
-    pub fn read(&self) -> String {
-        fs::read_to_string(&self.env.tmp_file_path).expect("failed to read temporarily file")
+impl ParseError {
+    fn new(line: usize, message: impl Into<String>) -> Self {
+        Self {
+            line,
+            message: message.into(),
+        }
     }
+}
 
-    pub fn diff(&self) -> Option<String> {
-        let tmp_file_value = self.read();
-        if &tmp_file_value[..] == TMP_FILE_DEFAULT_VALUE {
-            None
-        } else {
-            Some(tmp_file_value)
+/// Post parsed from the file written in the editor
+#[derive(Debug, PartialEq)]
+pub struct ParsedPost {
+    pub content: esa::post::PostContent,
+    /// `None` if not written
+    pub wip: Option<bool>,
+    pub message: Option<String>,
+    /// Revision which the edit is based on
+    pub revision_number: Option<i32>,
+}
+
+/// Parses the front matter format or the legacy format
+pub fn parse_post(content: &str) -> Result<ParsedPost, ParseError> {
+    match content.lines().next() {
+        Some(line) if line.trim_end() == FRONT_MATTER_DELIMITER => parse_front_matter(content),
+        Some(line) if line.trim_end() == LEGACY_NAME_MARKER => parse_legacy_post(content),
+        _ => Err(ParseError::new(
+            1,
+            format!(
+                "expected front matter starting with '{}'",
+                FRONT_MATTER_DELIMITER
+            ),
+        )),
+    }
+}
+
+fn parse_front_matter(content: &str) -> Result<ParsedPost, ParseError> {
+    let source = FrontMatterSource::new(content)?;
+    build_post(source.front_matter()?, source.body(), |key| {
+        source.line_of(key)
+    })
+}
+
+/// Splits a Markdown file into the front matter and the body.
+/// A file without front matter is the body only
+pub fn split_front_matter(content: &str) -> Result<(FrontMatter, String), ParseError> {
+    match content.lines().next() {
+        Some(line) if line.trim_end() == FRONT_MATTER_DELIMITER => {
+            let source = FrontMatterSource::new(content)?;
+            Ok((source.front_matter()?, source.body()))
         }
+        _ => Ok((FrontMatter::default(), content.to_string())),
     }
 }
 
-pub fn parse_post(content: &str) -> Result<esa::post::PostContent> {
-    let mut lines = content.lines();
-    if lines.next().is_none() {
-        return Err(anyhow!("failed to parse content"));
+/// Builds a post from the front matter given not in the editor
+pub fn post_from_front_matter(
+    front_matter: FrontMatter,
+    body: String,
+) -> Result<ParsedPost, ParseError> {
+    build_post(front_matter, body, |_| 1)
+}
+
+/// Lines of the content starting with the front matter
+struct FrontMatterSource<'a> {
+    content: &'a str,
+    lines: Vec<&'a str>,
+    /// Index of the closing delimiter
+    end: usize,
+}
+
+impl<'a> FrontMatterSource<'a> {
+    fn new(content: &'a str) -> Result<Self, ParseError> {
+        let lines = content.lines().collect::<Vec<_>>();
+        let end = lines
+            .iter()
+            .skip(1)
+            .position(|line| line.trim_end() == FRONT_MATTER_DELIMITER)
+            .map(|i| i + 1)
+            .ok_or_else(|| {
+                ParseError::new(
+                    1,
+                    format!(
+                        "front matter is not closed with '{}'",
+                        FRONT_MATTER_DELIMITER
+                    ),
+                )
+            })?;
+        Ok(Self {
+            content,
+            lines,
+            end,
+        })
+    }
+
+    /// 1-based line number of `key`
+    fn line_of(&self, key: &str) -> usize {
+        // front matter は 2 行目から始まる
+        self.lines[1..self.end]
+            .iter()
+            .position(|line| line.starts_with(&format!("{}:", key)))
+            .map(|i| i + 2)
+            .unwrap_or(2)
+    }
+
+    fn front_matter(&self) -> Result<FrontMatter, ParseError> {
+        let yaml = self.lines[1..self.end].join("\n");
+        if yaml.trim().is_empty() {
+            return Ok(FrontMatter::default());
+        }
+        serde_yaml::from_str(&yaml).map_err(|e| {
+            let message = e.to_string();
+            let message = match message.split_once(" at line ") {
+                Some((message, _)) => message.to_string(),
+                None => message,
+            };
+            // unknown field はマッピングの先頭位置が返るのでキーの行を探す
+            let unknown_field = message
+                .strip_prefix("unknown field `")
+                .and_then(|rest| rest.split_once('`'))
+                .map(|(field, _)| field);
+            let line = match unknown_field {
+                Some(field) => self.line_of(field),
+                None => e.location().map(|l| l.line() + 1).unwrap_or(2),
+            };
+            ParseError::new(line, message)
+        })
+    }
+
+    /// Text after the closing delimiter as it is, including the trailing newlines
+    fn body(&self) -> String {
+        let offset = self
+            .content
+            .split_inclusive('\n')
+            .take(self.end + 1)
+            .map(str::len)
+            .sum::<usize>();
+        self.content[offset..].to_string()
+    }
+}
+
+fn build_post(
+    front_matter: FrontMatter,
+    body: String,
+    line_of: impl Fn(&str) -> usize,
+) -> Result<ParsedPost, ParseError> {
+    let name = front_matter
+        .title
+        .map(|title| title.trim().to_string())
+        .filter(|title| !title.is_empty())
+        .ok_or_else(|| ParseError::new(line_of("title"), "title is required"))?;
+    let category = front_matter
+        .category
+        .map(|category| category.trim().trim_matches('/').to_string())
+        .filter(|category| !category.is_empty());
+    let tags = front_matter
+        .tags
+        .iter()
+        .map(|tag| tag.trim().trim_start_matches('#').to_string())
+        .collect::<Vec<_>>();
+    if tags.iter().any(|tag| tag.is_empty()) {
+        return Err(ParseError::new(line_of("tags"), "tags must not be empty"));
     }
+
+    Ok(ParsedPost {
+        content: esa::post::PostContent {
+            full_name: full_name(category.as_deref(), &name, &tags),
+            name,
+            body_md: Some(body),
+            tags,
+            category,
+        },
+        wip: front_matter.wip,
+        message: front_matter.message.filter(|m| !m.trim().is_empty()),
+        revision_number: front_matter.revision_number,
+    })
+}
+
+fn parse_legacy_post(content: &str) -> Result<ParsedPost, ParseError> {
+    let mut lines = content.lines();
+    lines.next();
     let title = lines
         .next()
-        .ok_or(anyhow!("failed to parse content. post name is required"))?;
+        .ok_or_else(|| ParseError::new(2, "post name is required"))?;
     let ParsedTitle {
         category,
         name,
         tags,
-    } = parse_title(title)?;
-    if lines.next().is_none() {
-        return Err(anyhow!("failed to parse content"));
+    } = parse_title(title).ok_or_else(|| ParseError::new(2, "post name is required"))?;
+    match lines.next() {
+        Some(line) if line.trim_end() == LEGACY_BODY_MARKER => {}
+        _ => {
+            return Err(ParseError::new(
+                3,
+                format!("expected '{}'", LEGACY_BODY_MARKER),
+            ))
+        }
     }
     let mut body = Vec::new();
     for line in lines {
         body.push(line);
     }
 
-    Ok(esa::post::PostContent {
-        name,
-        full_name: String::from(title),
-        body_md: Some(body.join("\n")),
-        tags,
-        category,
+    Ok(ParsedPost {
+        content: esa::post::PostContent {
+            name,
+            full_name: String::from(title),
+            body_md: Some(body.join("\n")),
+            tags,
+            category,
+        },
+        wip: None,
+        message: None,
+        revision_number: None,
     })
 }
 
+/// `category/name #tag1 #tag2`
+fn full_name(category: Option<&str>, name: &str, tags: &[String]) -> String {
+    let mut full_name = match category {
+        Some(category) => format!("{}/{}", category, name),
+        None => name.to_string(),
+    };
+    for tag in tags {
+        full_name.push_str(" #");
+        full_name.push_str(tag);
+    }
+    full_name
+}
+
 struct ParsedTitle {
     category: Option<String>,
     name: String,
     tags: Vec<String>,
 }
 
-fn parse_title(source: &str) -> Result<ParsedTitle> {
-    let (category, name, tags) = {
-        if source.is_empty() {
-            return Err(anyhow!("failed to parse content. post name is required"));
-        };
-        let title = source.split('/').collect::<Vec<_>>();
-        let category = title[..title.len() - 1].join("/");
-        let (name, tags) = parse_name_and_tags(title[title.len() - 1]);
-        (
-            if category.is_empty() {
-                None
-            } else {
-                Some(category)
-            },
-            name,
-            tags,
-        )
+fn parse_title(source: &str) -> Option<ParsedTitle> {
+    if source.is_empty() {
+        return None;
     };
-    Ok(ParsedTitle {
-        category,
+    let title = source.split('/').collect::<Vec<_>>();
+    let category = title[..title.len() - 1].join("/");
+    let (name, tags) = parse_name_and_tags(title[title.len() - 1]);
+    Some(ParsedTitle {
+        category: if category.is_empty() {
+            None
+        } else {
+            Some(category)
+        },
         name,
         tags,
     })
@@ -159,7 +648,7 @@ mod tests {
     use super::*;
 
     #[test]
-    fn test_parse_new_post() {
+    fn test_parse_legacy_post() {
         assert_eq!(
             esa::post::PostContent {
                 name: String::from("記事タイトル"),
@@ -176,9 +665,167 @@ mod tests {
 "#
             )
             .unwrap()
+            .content
         )
     }
 
+    #[test]
+    fn test_parse_front_matter_post() {
+        assert_eq!(
+            ParsedPost {
+                content: esa::post::PostContent {
+                    name: String::from("記事タイトル"),
+                    full_name: String::from("カテゴリ1/カテゴリ2/記事タイトル #tag1 #タグ2"),
+                    body_md: Some(String::from("記事 body\n---\n続き\n")),
+                    category: Some(String::from("カテゴリ1/カテゴリ2")),
+                    tags: vec![String::from("tag1"), String::from("タグ2")],
+                },
+                wip: Some(false),
+                message: Some(String::from("Update post")),
+                revision_number: Some(3),
+            },
+            parse_post(
+                r##"---
+title: 記事タイトル
+category: カテゴリ1/カテゴリ2
+tags: [tag1, "#タグ2"]
+wip: false
+message: Update post
+# read only
+number: 1
+revision_number: 3
+url: https://docs.esa.io/posts/1
+---
+記事 body
+---
+続き
+"##
+            )
+            .unwrap()
+        )
+    }
+
+    #[test]
+    fn test_format_and_parse_post() {
+        let front_matter = FrontMatter {
+            title: Some(String::from("記事: タイトル")),
+            category: Some(String::from("カテゴリ1")),
+            tags: vec![String::from("tag1")],
+            wip: Some(true),
+            number: Some(1),
+            revision_number: Some(3),
+            url: Some(String::from("https://docs.esa.io/posts/1")),
+            ..Default::default()
+        };
+        let parsed = parse_post(&format_post_content(&front_matter, "記事 body")).unwrap();
+        assert_eq!("記事: タイトル", parsed.content.name);
+        assert_eq!(Some(String::from("カテゴリ1")), parsed.content.category);
+        assert_eq!(vec![String::from("tag1")], parsed.content.tags);
+        assert_eq!(Some(String::from("記事 body")), parsed.content.body_md);
+        assert_eq!(Some(true), parsed.wip);
+    }
+
+    #[test]
+    fn test_format_keeps_null_in_values() {
+        let front_matter = FrontMatter {
+            title: Some(String::from("Handle null")),
+            tags: vec![String::from("null")],
+            ..Default::default()
+        };
+        let content = format_post_content(&front_matter, "body\n");
+        assert_eq!(
+            "---\ntitle: Handle null\ncategory:\ntags:\n- 'null'\nwip:\nmessage:\n---\nbody\n",
+            content
+        );
+        let (parsed, _) = split_front_matter(&content).unwrap();
+        assert_eq!(front_matter, parsed);
+    }
+
+    #[test]
+    fn test_parse_errors_have_line_numbers() {
+        assert_eq!(
+            ParseError::new(2, "title is required"),
+            parse_post(TMP_FILE_DEFAULT_VALUE).unwrap_err()
+        );
+        assert_eq!(
+            4,
+            parse_post("---\ntitle: 記事\ncategory: カテゴリ\nwip: maybe\n---\n")
+                .unwrap_err()
+                .line
+        );
+        assert_eq!(
+            3,
+            parse_post("---\ntitle: 記事\nwipp: true\n---\n")
+                .unwrap_err()
+                .line
+        );
+        assert_eq!(1, parse_post("---\ntitle: 記事\n").unwrap_err().line);
+        assert_eq!(
+            3,
+            parse_post("<!-- ### input post name next line ### -->\n記事\n記事 body\n")
+                .unwrap_err()
+                .line
+        );
+    }
+
+    #[test]
+    fn test_split_front_matter() {
+        let (front_matter, body) =
+            split_front_matter("---\ntitle: リリースノート\ntags: [release]\n---\n# v1.0\n")
+                .unwrap();
+        assert_eq!(Some(String::from("リリースノート")), front_matter.title);
+        assert_eq!(vec![String::from("release")], front_matter.tags);
+        assert_eq!("# v1.0\n", body);
+        let (_, body) = split_front_matter("---\ntitle: t\n---\n# v1.0\n\n\n").unwrap();
+        assert_eq!("# v1.0\n\n\n", body);
+
+        let (front_matter, body) = split_front_matter("# v1.0\n").unwrap();
+        assert_eq!(FrontMatter::default(), front_matter);
+        assert_eq!("# v1.0\n", body);
+    }
+
+    #[test]
+    fn test_editor_command() {
+        assert_eq!(
+            EditorCommand {
+                program: String::from("code"),
+                args: vec![String::from("--wait")],
+            },
+            EditorCommand::from_sources(
+                Some(String::from("code --wait")),
+                Some(String::from("vim")),
+                Some("nano")
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            EditorCommand {
+                program: String::from("/opt/my editor/bin/edit"),
+                args: vec![String::from("-t")],
+            },
+            EditorCommand::from_sources(
+                Some(String::new()),
+                Some(String::from("'/opt/my editor/bin/edit' -t")),
+                None
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            EditorCommand {
+                program: String::from("nano"),
+                args: vec![],
+            },
+            EditorCommand::from_sources(None, None, Some("nano")).unwrap()
+        );
+        assert_eq!(
+            DEFAULT_EDITOR,
+            EditorCommand::from_sources(None, None, None)
+                .unwrap()
+                .program
+        );
+        assert!(EditorCommand::from_sources(Some(String::from("vim 'a")), None, None).is_err());
+    }
+
     #[test]
     fn test_parse_name_and_tags() {
         assert_eq!(
@@ -0,0 +1,113 @@
+use std::collections::{BTreeSet, HashSet};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Result;
+use notify::{EventKind, RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+
+use esa::Esa;
+
+use super::config::Config;
+use super::rate_limit;
+use super::sync::{self, PushOptions, Pushed};
+use super::workspace::Workspace;
+
+/// Requests used to push a file. Pushing waits for the rate limit reset below this
+const REQUESTS_PER_PUSH: i32 = 3;
+
+/// Push files saved in the pulled directory until Ctrl-C
+/// # Args
+/// - dir: Pulled directory
+/// - debounce: Waits for saves to settle before pushing
+pub async fn watch(
+    esa: &Esa,
+    config: &Config,
+    dir: &Path,
+    options: &PushOptions,
+    debounce: Duration,
+) -> Result<()> {
+    let mut workspace = Workspace::find(dir)?;
+    sync::check_team(esa, &workspace)?;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher =
+        notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+            Ok(event) if matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) => {
+                for path in event.paths {
+                    let _ = tx.send(path);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => log::warn!("watch error: {}", e),
+        })?;
+    watcher.watch(&workspace.root, RecursiveMode::Recursive)?;
+    println!(
+        "watching {}. press Ctrl-C to stop",
+        workspace.root.display()
+    );
+
+    loop {
+        let mut changed = BTreeSet::new();
+        tokio::select! {
+            path = rx.recv() => match path {
+                Some(path) => changed.insert(path),
+                None => break,
+            },
+            _ = tokio::signal::ctrl_c() => break,
+        };
+        // 保存が落ち着くまでまとめて待つ
+        while let Ok(Some(path)) = tokio::time::timeout(debounce, rx.recv()).await {
+            changed.insert(path);
+        }
+
+        for path in changed {
+            if let Some(path) = markdown_path(&workspace, &path) {
+                push(esa, config, &mut workspace, &path, options).await?;
+            }
+        }
+    }
+    println!("stopped watching");
+    Ok(())
+}
+
+/// Path relative to the directory if it is a Markdown file to push
+fn markdown_path(workspace: &Workspace, path: &Path) -> Option<String> {
+    if !path.is_file() {
+        return None;
+    }
+    let path = workspace.relative(path).ok()?;
+    // エディタの一時ファイルや manifest は対象外
+    let hidden = path.split('/').any(|segment| segment.starts_with('.'));
+    if hidden || !path.ends_with(".md") {
+        return None;
+    }
+    Some(path)
+}
+
+async fn push(
+    esa: &Esa,
+    config: &Config,
+    workspace: &mut Workspace,
+    path: &str,
+    options: &PushOptions,
+) -> Result<()> {
+    for retry in [false, true] {
+        rate_limit::wait(esa, REQUESTS_PER_PUSH).await;
+        let result =
+            sync::push_file(esa, config, workspace, path, options, &mut HashSet::new()).await;
+        match result {
+            Ok(Pushed::Created) | Ok(Pushed::Updated) => {
+                workspace.save()?;
+                return Ok(());
+            }
+            Ok(_) => return Ok(()),
+            Err(e) if !retry && rate_limit::is_rate_limited(&e) => continue,
+            Err(e) => {
+                eprintln!("failed to push {}: {:#}", path, e);
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
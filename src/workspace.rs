@@ -0,0 +1,305 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use chrono::{prelude::Local, DateTime};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use esa::{post::Post, Esa, TeamId};
+
+use super::paging;
+use super::tmp_file::{self, FrontMatter};
+
+/// Manifest file at the top of a pulled directory
+pub const MANIFEST_FILE: &str = ".esa-manifest.json";
+
+/// What was pulled into the directory
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct Manifest {
+    pub team: String,
+    /// Search query used to pull. `None` pulls all posts
+    pub query: Option<String>,
+    pub posts: Vec<Entry>,
+}
+
+/// A post pulled as a file
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone)]
+pub struct Entry {
+    /// Path relative to the directory separated by `/`
+    pub path: String,
+    pub number: i32,
+    pub revision_number: i32,
+    pub updated_at: DateTime<Local>,
+    /// SHA-256 of the file as written by pull or push
+    pub hash: String,
+}
+
+/// Directory mirroring posts of a category or a query
+#[derive(Debug)]
+pub struct Workspace {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Workspace {
+    /// Opens the pulled directory
+    pub fn open(root: &Path) -> Result<Self> {
+        let manifest_path = root.join(MANIFEST_FILE);
+        let manifest = fs::read_to_string(&manifest_path).with_context(|| {
+            format!(
+                "{} is not found. `esa-cli pull` the directory first",
+                manifest_path.display()
+            )
+        })?;
+        let manifest = serde_json::from_str(&manifest)
+            .with_context(|| format!("invalid manifest {}", manifest_path.display()))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            manifest,
+        })
+    }
+
+    /// Opens the pulled directory containing `path`
+    pub fn find(path: &Path) -> Result<Self> {
+        let path = path
+            .canonicalize()
+            .with_context(|| format!("{} is not found", path.display()))?;
+        match path
+            .ancestors()
+            .find(|dir| dir.join(MANIFEST_FILE).is_file())
+        {
+            Some(root) => Self::open(root),
+            None => bail!("{} is not in a pulled directory", path.display()),
+        }
+    }
+
+    /// Opens the directory pulled before or starts a new one
+    pub fn open_or_create(root: &Path, team: &TeamId, query: Option<String>) -> Result<Self> {
+        if root.join(MANIFEST_FILE).exists() {
+            let workspace = Self::open(root)?;
+            if workspace.manifest.team != team.to_string() {
+                bail!(
+                    "{} is pulled from team '{}'",
+                    root.display(),
+                    workspace.manifest.team
+                );
+            }
+            if workspace.manifest.query != query {
+                bail!(
+                    "{} is pulled with query '{}'",
+                    root.display(),
+                    workspace.manifest.query.as_deref().unwrap_or("")
+                );
+            }
+            return Ok(workspace);
+        }
+        Ok(Self {
+            root: root.to_path_buf(),
+            manifest: Manifest {
+                team: team.to_string(),
+                query,
+                posts: Vec::new(),
+            },
+        })
+    }
+
+    pub fn save(&mut self) -> Result<()> {
+        self.manifest.posts.sort_by_key(|entry| entry.number);
+        fs::create_dir_all(&self.root)
+            .with_context(|| format!("failed to create {}", self.root.display()))?;
+        let path = self.root.join(MANIFEST_FILE);
+        let manifest = serde_json::to_string_pretty(&self.manifest)?;
+        fs::write(&path, manifest).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    pub fn entry(&self, number: i32) -> Option<&Entry> {
+        self.manifest
+            .posts
+            .iter()
+            .find(|entry| entry.number == number)
+    }
+
+    pub fn entry_by_path(&self, path: &str) -> Option<&Entry> {
+        self.manifest.posts.iter().find(|entry| entry.path == path)
+    }
+
+    /// Adds or replaces the entry of the post
+    pub fn set_entry(&mut self, entry: Entry) {
+        self.manifest.posts.retain(|e| e.number != entry.number);
+        self.manifest.posts.push(entry);
+    }
+
+    pub fn full_path(&self, path: &str) -> PathBuf {
+        self.root.join(path)
+    }
+
+    pub fn read(&self, path: &str) -> Result<Option<String>> {
+        let full_path = self.full_path(path);
+        if !full_path.exists() {
+            return Ok(None);
+        }
+        fs::read_to_string(&full_path)
+            .map(Some)
+            .with_context(|| format!("failed to read {}", full_path.display()))
+    }
+
+    pub fn write(&self, path: &str, content: &str) -> Result<()> {
+        let full_path = self.full_path(path);
+        if let Some(dir) = full_path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("failed to create {}", dir.display()))?;
+        }
+        fs::write(&full_path, content)
+            .with_context(|| format!("failed to write {}", full_path.display()))
+    }
+
+    /// Whether the file differs from the one pulled or pushed last
+    pub fn is_modified(&self, entry: &Entry) -> Result<bool> {
+        Ok(match self.read(&entry.path)? {
+            Some(content) => hash(&content) != entry.hash,
+            None => true,
+        })
+    }
+
+    /// Path for the post not used by another post
+    pub fn path_for(&self, post: &Post) -> String {
+        let mut dir = post
+            .category
+            .as_deref()
+            .unwrap_or("")
+            .split('/')
+            .filter(|segment| !segment.is_empty())
+            .map(sanitize)
+            .collect::<Vec<_>>()
+            .join("/");
+        if !dir.is_empty() {
+            dir.push('/');
+        }
+        let name = sanitize(&post.name);
+        let path = format!("{}{}.md", dir, name);
+        // 他の記事や pull していないファイルが使っているパスは避ける
+        let used = match self.entry_by_path(&path) {
+            Some(entry) => entry.number != post.number,
+            None => self.full_path(&path).exists(),
+        };
+        if used {
+            format!("{}{}-{}.md", dir, name, post.number)
+        } else {
+            path
+        }
+    }
+
+    /// Markdown files under the directory relative to it. Hidden files are skipped
+    pub fn files(&self) -> Result<Vec<String>> {
+        let mut files = Vec::new();
+        collect_files(&self.root, "", &mut files)?;
+        files.retain(|path| path.ends_with(".md"));
+        files.sort();
+        Ok(files)
+    }
+
+    /// Path relative to the directory separated by `/`. Empty for the directory itself
+    pub fn relative(&self, path: &Path) -> Result<String> {
+        let full_path = path
+            .canonicalize()
+            .with_context(|| format!("{} is not found", path.display()))?;
+        let root = self.root.canonicalize()?;
+        let relative = full_path
+            .strip_prefix(&root)
+            .with_context(|| format!("{} is not in {}", path.display(), self.root.display()))?;
+        Ok(relative
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"))
+    }
+
+    /// Query to fetch the posts of the directory
+    pub fn search_query(&self) -> esa::post::SearchQuery {
+        esa::post::SearchQuery::new(self.manifest.query.clone(), None, None)
+    }
+}
+
+/// All files under the directory relative to it. Hidden files are skipped
+pub fn collect_files(dir: &Path, prefix: &str, files: &mut Vec<String>) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().to_string();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = format!("{}{}", prefix, name);
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &format!("{}/", path), files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Fetches all pages of the posts in the directory
+pub async fn fetch_posts(esa: &Esa, workspace: &Workspace) -> Result<Vec<Post>> {
+    paging::fetch_all(esa, workspace.search_query()).await
+}
+
+/// File content of the post
+pub fn format_post(post: &Post) -> String {
+    tmp_file::format_post_content(&FrontMatter::from_post(post), &post.body_md)
+}
+
+pub fn entry_of(post: &Post, path: String, content: &str) -> Entry {
+    Entry {
+        path,
+        number: post.number,
+        revision_number: post.revision_number,
+        updated_at: post.updated_at,
+        hash: hash(content),
+    }
+}
+
+pub fn hash(content: &str) -> String {
+    Sha256::digest(content.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Replaces characters which can not be used in file names
+pub fn sanitize(segment: &str) -> String {
+    let sanitized = segment
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect::<String>();
+    match sanitized.trim() {
+        "" | "." | ".." => String::from("_"),
+        s if s.starts_with('.') => format!("_{}", s),
+        s => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!("日報 2021_03_07", sanitize("日報 2021:03:07"));
+        assert_eq!("_", sanitize(".."));
+        assert_eq!("_.hidden", sanitize(".hidden"));
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            hash("")
+        );
+    }
+}